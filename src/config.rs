@@ -1,20 +1,31 @@
+use crate::harvest::BreakRule;
+use crate::harvest::HoursFormat;
+use crate::harvest::RoundMode;
 use crate::harvest::Task;
+use crate::harvest::REQUIRED_CSV_COLUMN_NAMES;
+use calvest::ical::parse::cal_address;
 use chrono::DateTime;
 use chrono::Datelike;
 use chrono::Days;
-use chrono::Local;
 use chrono::Months;
-use chrono::NaiveDateTime;
+use chrono::NaiveDate;
 use chrono::NaiveTime;
 use chrono::Utc;
+use chrono::Weekday;
+use chrono_tz::Tz;
 use clap::builder::NonEmptyStringValueParser;
+use clap::error::ErrorKind;
 use clap::Arg;
 use clap::ArgAction;
+use clap::ArgMatches;
 use clap::Command;
 use clap::ValueEnum;
 use clap_complete::Shell;
 use regex::Regex;
+use regex::RegexBuilder;
+use serde::Deserialize;
 use std::collections::HashSet;
+use std::path::Path;
 use std::path::PathBuf;
 
 fn wrap_at<S: ToString>(s: S, at: usize) -> String {
@@ -41,101 +52,757 @@ fn wrap_help<S: ToString>(s: S) -> String {
 pub(crate) struct TaskPattern {
     pub(crate) task: Task,
     pub(crate) regex: Regex,
+    /// Rounds the matching works unless --round-to is given.
+    pub(crate) rounding: Option<(i64, RoundMode)>,
+    /// Logs these hours for each matching work instead of its duration.
+    pub(crate) hours: Option<f64>,
+    /// Formats the notes of the matching works instead of --notes-template.
+    pub(crate) notes_template: Option<String>,
+    /// Whether the matching works are logged at all.
+    pub(crate) billable: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct CategoryTask {
+    pub(crate) category: String,
+    pub(crate) task: Task,
+}
+
+/// The contract terms of a client: each entry is rounded up to `increment`
+/// minutes and bills at least `minimum` minutes.
+#[derive(Debug)]
+pub(crate) struct ClientBilling {
+    pub(crate) client: String,
+    pub(crate) increment: i64,
+    pub(crate) minimum: i64,
 }
 
 #[derive(Debug)]
 pub(crate) struct Config {
-    pub(crate) input: Option<PathBuf>,
+    pub(crate) input: Vec<PathBuf>,
+    pub(crate) input_encoding: InputEncoding,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) http_timeout: u64,
+    pub(crate) lenient_input: bool,
     pub(crate) output: Option<PathBuf>,
+    pub(crate) format: OutputFormat,
+    pub(crate) also_json: Option<PathBuf>,
+    pub(crate) also_jsonl: Option<PathBuf>,
+    pub(crate) split_by_month: Option<PathBuf>,
     pub(crate) extra_props: Vec<String>,
     pub(crate) first_name: String,
     pub(crate) last_name: String,
     pub(crate) default_task: Task,
+    pub(crate) all_day_hours: f64,
+    pub(crate) default_hours: Option<f64>,
+    pub(crate) ignore_rrule: bool,
+    pub(crate) infer_end_from_next: bool,
+    pub(crate) max_inferred_hours: f64,
+    pub(crate) min_duration: Option<i64>,
+    pub(crate) break_rule: Option<BreakRule>,
+    pub(crate) split_multiday: bool,
+    pub(crate) clamp_to_day: bool,
+    pub(crate) normalize_day: Option<f64>,
+    pub(crate) client_billing: Vec<ClientBilling>,
+    pub(crate) dedup_overlaps: Option<OverlapStrategy>,
+    pub(crate) merge_gap: Option<i64>,
+    pub(crate) merge_when_notes_match: Option<Regex>,
+    pub(crate) unique_notes: bool,
+    pub(crate) aggregate_daily: bool,
+    pub(crate) round_to: Option<i64>,
+    pub(crate) round_mode: RoundMode,
+    pub(crate) hours_format: HoursFormat,
+    /// The timezone of the Date column and of the day boundaries.
+    pub(crate) timezone: Tz,
     pub(crate) start_date: Option<DateTime<Utc>>,
     pub(crate) end_date: Option<DateTime<Utc>>,
+    pub(crate) date_format: String,
+    pub(crate) only_past: bool,
+    pub(crate) only_future: bool,
     pub(crate) tasks: Vec<TaskPattern>,
+    pub(crate) category_tasks: Vec<CategoryTask>,
+    /// The fixed hours logged for the works whose summary matches the regex.
+    pub(crate) task_hours: Vec<(Regex, f64)>,
+    pub(crate) notes_from: Vec<NotesSource>,
+    pub(crate) exclude_patterns: Vec<Regex>,
+    pub(crate) skip_decorative: bool,
     pub(crate) required_attendies: HashSet<String>,
+    /// The PARTSTAT values that count as the attendee participating.
+    pub(crate) accepted_states: Vec<&'static str>,
+    pub(crate) my_email: Option<String>,
+    pub(crate) location: Option<Regex>,
+    pub(crate) exclude_location: Option<Regex>,
+    pub(crate) drop_notes_after: Option<Regex>,
+    pub(crate) strip_notes: Vec<Regex>,
+    pub(crate) strip_notes_replacement: String,
+    pub(crate) notes_template: Option<String>,
+    pub(crate) with_organizer: bool,
+    pub(crate) with_uid: bool,
+    pub(crate) with_minutes: bool,
+    pub(crate) with_summary: bool,
+    pub(crate) with_status: bool,
+    pub(crate) name_style: NameStyle,
+    pub(crate) name_combine_format: String,
+    pub(crate) sort: Option<SortOrder>,
+    pub(crate) fail_on_empty: bool,
+    pub(crate) post_hook: Option<String>,
+    pub(crate) max_rows: Option<usize>,
+    pub(crate) verbosity: u8,
+    pub(crate) quiet: bool,
+    pub(crate) max_warnings: Option<usize>,
+    pub(crate) report_conflicts: bool,
+    pub(crate) dry_run: bool,
+    pub(crate) summary_only: bool,
+    pub(crate) suggest_tasks: bool,
+    pub(crate) validate_harvest: bool,
+    pub(crate) preview_count: Option<usize>,
+    pub(crate) reverse: bool,
+}
+
+impl Config {
+    /// The columns written after the included properties, as the flags set them.
+    pub(crate) fn column_names(&self) -> Vec<&'static str> {
+        let combined_name = matches!(self.name_style, NameStyle::Combined);
+        REQUIRED_CSV_COLUMN_NAMES
+            .iter()
+            .copied()
+            .filter(|c| !combined_name || !matches!(*c, "First name" | "Last name"))
+            .chain(combined_name.then_some("Name"))
+            .chain(self.with_organizer.then_some("Organizer"))
+            .chain(self.with_uid.then_some("UID"))
+            .chain(self.with_minutes.then_some("Minutes"))
+            .chain(self.with_summary.then_some("Summary"))
+            .chain(self.with_status.then_some("Status"))
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct TaskEntry {
+    name: String,
+    project: String,
+    project_code: String,
+    client: String,
+}
+
+impl From<TaskEntry> for Task {
+    fn from(entry: TaskEntry) -> Self {
+        Task {
+            name: entry.name,
+            project: entry.project,
+            project_code: entry.project_code,
+            client: entry.client,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct TaskPatternEntry {
+    name: String,
+    project: String,
+    project_code: String,
+    client: String,
+    regex: String,
+    round_to: Option<i64>,
+    round_mode: Option<RoundMode>,
+    hours: Option<f64>,
+    notes_template: Option<String>,
+    #[serde(default = "billable_default")]
+    billable: bool,
+}
+
+fn billable_default() -> bool {
+    true
+}
+
+/// The TOML file given with `--config`.
+///
+/// Values given on the command line take precedence over the file values.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct ConfigFile {
+    first_name: Option<String>,
+    last_name: Option<String>,
+    default_task: Option<TaskEntry>,
+    #[serde(default)]
+    tasks: Vec<TaskPatternEntry>,
+    #[serde(default)]
+    include_properties: Vec<String>,
+    #[serde(default)]
+    required_attendees: Vec<String>,
+}
+
+impl ConfigFile {
+    fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read the config file {path:?}: {e}"))?;
+        toml::from_str(&content).map_err(|e| format!("Invalid config file {path:?}: {e}"))
+    }
+}
+
+fn date_value(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|e| e.to_string())
 }
 
-fn date_str_to_datetime(s: &str) -> Result<DateTime<Utc>, String> {
-    Ok(
-        NaiveDateTime::parse_from_str(&(s.to_string() + "000000"), "%Y-%m-%d%H%M%S")
-            .map_err(|e| e.to_string())?
-            .and_utc(),
-    )
+fn timezone_value(s: &str) -> Result<Tz, String> {
+    s.parse::<Tz>()
+        .map_err(|_| format!("not an IANA timezone name, e.g., Europe/Berlin: '{s}'"))
+}
+
+/// The timezone of the system, as `TZ` or `/etc/localtime` set it, or UTC.
+fn system_timezone() -> Tz {
+    std::env::var("TZ")
+        .ok()
+        .and_then(|tz| tz.trim_start_matches(':').parse::<Tz>().ok())
+        .or_else(|| {
+            iana_time_zone::get_timezone()
+                .ok()
+                .and_then(|tz| tz.parse::<Tz>().ok())
+        })
+        .unwrap_or(Tz::UTC)
+}
+
+/// Accepts a strftime format that formats a date without time.
+fn date_format_value(s: &str) -> Result<String, String> {
+    use std::fmt::Write;
+    let mut formatted = String::new();
+    write!(formatted, "{}", NaiveDate::MIN.format(s))
+        .map_err(|_| format!("not a valid date format: '{s}'"))?;
+    Ok(s.to_string())
+}
+
+fn break_rule_value(s: &str) -> Result<BreakRule, String> {
+    let invalid = || format!("expected HOURS:BREAK_MINUTES, e.g., 4:30, got '{s}'");
+    let (hours, minutes) = s.split_once(':').ok_or_else(invalid)?;
+    let hours = hours.parse::<f64>().map_err(|_| invalid())?;
+    let minutes = minutes.parse::<i64>().map_err(|_| invalid())?;
+    if hours < 0.0 || minutes < 0 {
+        return Err(invalid());
+    }
+    Ok(BreakRule {
+        over_minutes: (hours * 60.0).round() as i64,
+        minutes,
+    })
+}
+
+fn header_value(s: &str) -> Result<(String, String), String> {
+    let invalid = || format!("expected NAME: VALUE, e.g., 'Cache-Control: no-cache', got '{s}'");
+    let (name, value) = s.split_once(':').ok_or_else(invalid)?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(invalid());
+    }
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+/// The non-empty value of the environment variable.
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn regex_value(s: &str) -> Result<Regex, String> {
+    Regex::new(s).map_err(|e| e.to_string())
+}
+
+fn pattern_regex(
+    pattern: &str,
+    ignore_case: bool,
+    anchor: MatchAnchor,
+) -> Result<Regex, regex::Error> {
+    let pattern = match anchor {
+        MatchAnchor::Substring => pattern.to_string(),
+        MatchAnchor::Full => format!("^(?:{pattern})$"),
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum MatchAnchor {
+    /// The regex matches any part of the summary.
+    Substring,
+    /// The regex matches the whole summary.
+    Full,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum OutputFormat {
+    Csv,
+    /// A single JSON array of objects keyed by the column names.
+    Json,
+    /// A JSON object per line keyed by the column names.
+    Jsonl,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum InputEncoding {
+    Utf8,
+    /// Windows-1252, a superset of ISO-8859-1 used by older Outlook exports.
+    Latin1,
+    /// UTF-8 if the input is valid UTF-8, otherwise Windows-1252.
+    Auto,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum NotesSource {
+    Summary,
+    Description,
+    Location,
+}
+
+impl NotesSource {
+    pub(crate) fn property_name(self) -> &'static str {
+        match self {
+            Self::Summary => "SUMMARY",
+            Self::Description => "DESCRIPTION",
+            Self::Location => "LOCATION",
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum NameStyle {
+    /// The "First name" and "Last name" columns.
+    Separate,
+    /// A single "Name" column formatted with --name-combine-format.
+    Combined,
+}
+
+/// How the overlapping works are reduced to one.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum OverlapStrategy {
+    /// One work spanning all the overlapping works.
+    Merge,
+    /// Only the longest of the overlapping works.
+    KeepLongest,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+pub(crate) enum SortOrder {
+    /// By the start date and time.
+    Date,
+    /// By the start date, then by the task name, then by the start time.
+    DateTask,
 }
 
 #[derive(ValueEnum, Clone)]
 enum Period {
     LastMonth,
     ThisMonth,
+    LastWeek,
+    ThisWeek,
+    /// From the start of the year up to the end of today.
+    #[value(name = "ytd")]
+    YearToDate,
+}
+
+/// The first moment of the `date` in the `tz` timezone, which is later than
+/// 00:00 if a DST transition skips the midnight.
+pub(crate) fn midnight(date: NaiveDate, tz: Tz) -> DateTime<Tz> {
+    let mut time = date.and_time(NaiveTime::MIN);
+    loop {
+        if let Some(dt) = time.and_local_timezone(tz).earliest() {
+            return dt;
+        }
+        time += chrono::Duration::minutes(1);
+    }
 }
 
-fn current_month_start() -> DateTime<Local> {
-    Local::now()
-        .with_day(1)
-        .unwrap()
-        .with_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap())
-        .unwrap()
+fn month_start(date: NaiveDate, tz: Tz) -> DateTime<Tz> {
+    midnight(date.with_day(1).unwrap(), tz)
 }
 
-fn prev_month_start(dt: &DateTime<Local>) -> DateTime<Local> {
-    dt.with_day(1)
-        .unwrap()
-        .checked_sub_months(Months::new(1))
-        .unwrap()
+fn prev_month_start(dt: &DateTime<Tz>) -> DateTime<Tz> {
+    let date = dt.date_naive().with_day(1).unwrap() - Months::new(1);
+    midnight(date, dt.timezone())
 }
 
-fn next_month_start(dt: &DateTime<Local>) -> DateTime<Local> {
-    dt.with_day(1)
-        .unwrap()
-        .checked_add_months(Months::new(1))
-        .unwrap()
+fn next_month_start(dt: &DateTime<Tz>) -> DateTime<Tz> {
+    let date = dt.date_naive().with_day(1).unwrap() + Months::new(1);
+    midnight(date, dt.timezone())
 }
 
-fn cli() -> clap::Command {
+impl Period {
+    /// The start and the (exclusive) end of the period containing `today`.
+    fn range(&self, today: NaiveDate, tz: Tz) -> (DateTime<Tz>, DateTime<Tz>) {
+        let week_start = midnight(today.week(Weekday::Mon).first_day(), tz);
+        match self {
+            Self::LastMonth => {
+                let end_date = month_start(today, tz);
+                (prev_month_start(&end_date), end_date)
+            }
+            Self::ThisMonth => {
+                let start_date = month_start(today, tz);
+                let end_date = next_month_start(&start_date);
+                (start_date, end_date)
+            }
+            Self::LastWeek => {
+                let start_date = midnight(week_start.date_naive() - Days::new(7), tz);
+                (start_date, week_start)
+            }
+            Self::ThisWeek => {
+                let end_date = midnight(week_start.date_naive() + Days::new(7), tz);
+                (week_start, end_date)
+            }
+            Self::YearToDate => {
+                let start_date = midnight(today.with_ordinal(1).unwrap(), tz);
+                let end_date = midnight(today + Days::new(1), tz);
+                (start_date, end_date)
+            }
+        }
+    }
+}
+
+pub(crate) fn cli() -> clap::Command {
     Command::new(clap::crate_name!())
         .author(clap::crate_authors!())
         .version(clap::crate_version!())
         .long_version(clap::crate_version!())
         .about(clap::crate_description!())
         .args([
+            Arg::new("config")
+                .long("config")
+                .value_name("FILE")
+                .help(wrap_help(
+                    [
+                        "Read the names, the default task, the tasks, the included properties,",
+                        "and the required attendees from the TOML <FILE>.",
+                        "The command line options take precedence.",
+                        "The events matching a task with `billable = false` are not logged at all.",
+                    ]
+                    .join(" "),
+                ))
+                .value_parser(clap::value_parser!(PathBuf))
+                .num_args(1),
             Arg::new("input")
                 .long("input")
                 .value_name("FILE")
-                .help("Read the ical data from <FILE> instead of <stdin>.")
+                .help(wrap_help(
+                    [
+                        "Read the ical data from <FILE> instead of <stdin>.",
+                        "An http:// or https:// URL is fetched, e.g., a calendar subscription.",
+                        "Repeat to merge several calendars;",
+                        "the events shared between them are logged once.",
+                        "Gzipped input, e.g., an .ics.gz export, is decompressed.",
+                    ]
+                    .join(" "),
+                ))
                 .value_parser(clap::value_parser!(PathBuf))
-                .required_unless_present("print-completions")
+                .action(ArgAction::Append)
                 .num_args(1),
+            Arg::new("input-encoding")
+                .long("input-encoding")
+                .value_name("ENCODING")
+                .value_parser(clap::value_parser!(InputEncoding))
+                .default_value("utf8")
+                .num_args(1)
+                .help("Read the ical data in this encoding."),
+            Arg::new("lenient-input")
+                .long("lenient-input")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "Accept an input of bare VEVENTs without the surrounding VCALENDAR,",
+                        "e.g., a single pasted event.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("header")
+                .long("header")
+                .value_name("NAME: VALUE")
+                .value_parser(header_value)
+                .action(ArgAction::Append)
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Send this header when an --input is an http(s) URL,",
+                        "e.g., 'Authorization: Bearer <TOKEN>' or 'Cache-Control: no-cache'.",
+                        "Repeat to send several headers.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("http-timeout")
+                .long("http-timeout")
+                .value_name("SECONDS")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("30")
+                .num_args(1)
+                .help("Give up fetching an http(s) --input after <SECONDS>."),
             Arg::new("output")
                 .long("output")
                 .value_name("FILE")
                 .help("Write the result into the <FILE> instead of printing to <stdout>.")
                 .value_parser(clap::value_parser!(PathBuf))
-                .required_unless_present("print-completions")
+                .num_args(1),
+            Arg::new("format")
+                .long("format")
+                .value_name("FORMAT")
+                .value_parser(clap::value_parser!(OutputFormat))
+                .default_value("csv")
+                .num_args(1)
+                .help("Write the result in this format."),
+            Arg::new("also-json")
+                .long("also-json")
+                .value_name("FILE")
+                .help("Also write the result as a JSON array into the <FILE>.")
+                .value_parser(clap::value_parser!(PathBuf))
+                .num_args(1),
+            Arg::new("also-jsonl")
+                .long("also-jsonl")
+                .value_name("FILE")
+                .help("Also write the result as JSON Lines into the <FILE>.")
+                .value_parser(clap::value_parser!(PathBuf))
+                .num_args(1),
+            Arg::new("split-by-month")
+                .long("split-by-month")
+                .value_name("DIR")
+                .help(wrap_help(
+                    [
+                        "Also write the result into a CSV file per month of the Date column,",
+                        "named <DIR>/YYYY-MM.csv.",
+                    ]
+                    .join(" "),
+                ))
+                .value_parser(clap::value_parser!(PathBuf))
                 .num_args(1),
             Arg::new("default-task")
                 .long("default-task")
-                .value_names(&["TASK_NAME", "PROJECT_NAME", "PROJECT_CODE", "CLIENT_NAME"])
+                .value_names(["TASK_NAME", "PROJECT_NAME", "PROJECT_CODE", "CLIENT_NAME"])
                 .action(ArgAction::Append)
                 .value_parser(NonEmptyStringValueParser::new())
                 .num_args(4)
-                .required_unless_present("print-completions")
-                .help("Set the default task with the task name."),
+                .help(wrap_help(
+                    [
+                        "Set the default task with the task name.",
+                        "Defaults to $CALVEST_DEFAULT_TASK, the four values separated by ';'.",
+                    ]
+                    .join(" "),
+                )),
             Arg::new("first-name")
                 .long("first-name")
                 .value_name("FIRST_NAME")
                 .num_args(1)
                 .value_parser(NonEmptyStringValueParser::new())
-                .required_unless_present("print-completions")
-                .help("Set the employe first name."),
+                .help("Set the employe first name. Defaults to $CALVEST_FIRST_NAME."),
             Arg::new("last-name")
                 .long("last-name")
                 .value_name("LAST_NAME")
                 .value_parser(NonEmptyStringValueParser::new())
                 .num_args(1)
-                .required_unless_present("print-completions")
-                .help("Set the employe last name."),
+                .help("Set the employe last name. Defaults to $CALVEST_LAST_NAME."),
+            Arg::new("all-day-hours")
+                .long("all-day-hours")
+                .value_name("HOURS")
+                .value_parser(clap::value_parser!(f64))
+                .default_value("8")
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Log this many hours for each day of an all-day event.",
+                        "The events with a time but no end use --default-hours instead.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("default-hours")
+                .long("default-hours")
+                .value_name("HOURS")
+                .value_parser(clap::value_parser!(f64))
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Log this many hours for an event with a time but no DTEND or DURATION",
+                        "instead of skipping it. The all-day events use --all-day-hours instead.",
+                        "With --infer-end-from-next, only the last such event does.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("ignore-rrule")
+                .long("ignore-rrule")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "Do not expand the recurring events;",
+                        "log only the first occurrence of each.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("infer-end-from-next")
+                .long("infer-end-from-next")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "End the events that have neither DTEND nor DURATION",
+                        "at the start of the next event.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("max-inferred-hours")
+                .long("max-inferred-hours")
+                .value_name("HOURS")
+                .value_parser(clap::value_parser!(f64))
+                .default_value("2")
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Limit the duration inferred with --infer-end-from-next.",
+                        "The last event without an end lasts --default-hours,",
+                        "or this long without it.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("min-duration")
+                .long("min-duration")
+                .value_name("MINUTES")
+                .value_parser(clap::value_parser!(i64).range(0..))
+                .num_args(1)
+                .help("Skip the events shorter than <MINUTES>."),
+            Arg::new("subtract-break-over")
+                .long("subtract-break-over")
+                .value_name("HOURS:BREAK_MINUTES")
+                .value_parser(break_rule_value)
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Subtract <BREAK_MINUTES> from the events longer than <HOURS>;",
+                        "for example, 4:30 takes a 30-minute lunch break off the events over 4 hours.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("split-multiday")
+                .long("split-multiday")
+                .action(ArgAction::SetTrue)
+                .help("Log the events crossing midnight as one work entry per day."),
+            Arg::new("clamp-to-day")
+                .long("clamp-to-day")
+                .conflicts_with("split-multiday")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "End the events crossing the local midnight at that midnight.",
+                        "An event from 23:30 to 00:30 logs 0.50 hours on its start date;",
+                        "ending at 23:59:59 instead would drop the last minute.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("normalize-day")
+                .long("normalize-day")
+                .value_name("HOURS")
+                .value_parser(clap::value_parser!(f64))
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Scale the logged time of each day proportionally",
+                        "so that the day totals exactly <HOURS>.",
+                        "Days without logged time are left as is.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("client-billing")
+                .long("client-billing")
+                .value_names(["CLIENT_NAME", "INCREMENT", "MINIMUM"])
+                .value_parser(NonEmptyStringValueParser::new())
+                .action(ArgAction::Append)
+                .num_args(3)
+                .help(wrap_help(
+                    [
+                        "Round each work of the client up to <INCREMENT> minutes",
+                        "and bill at least <MINIMUM> minutes per work.",
+                        "Takes precedence over --round-to and the task rounding.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("dedup-overlaps")
+                .long("dedup-overlaps")
+                .value_name("STRATEGY")
+                .value_parser(clap::value_parser!(OverlapStrategy))
+                .num_args(1)
+                .help("Reduce each group of overlapping works on the same date to a single work."),
+            Arg::new("merge-gap")
+                .long("merge-gap")
+                .visible_alias("merge-adjacent")
+                .value_name("MINUTES")
+                .value_parser(clap::value_parser!(i64).range(0..))
+                .num_args(0..=1)
+                .default_missing_value("0")
+                .help(wrap_help(
+                    [
+                        "Merge the consecutive works of the same task on the same date",
+                        "separated by at most <MINUTES>, 0 by default, into one work.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("aggregate-daily")
+                .long("aggregate-daily")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "Write a single work per date and task with the total time,",
+                        "the distinct notes joined with '; ',",
+                        "and the first non-empty value of each included property.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("merge-when-notes-match")
+                .long("merge-when-notes-match")
+                .value_name("REGEX")
+                .value_parser(regex_value)
+                .requires("merge-gap")
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Only merge the works (see --merge-gap)",
+                        "whose notes both match <REGEX>.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("unique-notes")
+                .long("unique-notes")
+                .action(ArgAction::SetTrue)
+                .requires("merge-gap")
+                .help(wrap_help(
+                    [
+                        "Join the distinct notes of the merged works (see --merge-gap)",
+                        "with '; ' in the first-seen order",
+                        "instead of keeping the notes of the first work.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("hours-precision")
+                .long("hours-precision")
+                .value_name("DIGITS")
+                .value_parser(clap::value_parser!(u8).range(0..=6))
+                .default_value("2")
+                .num_args(1)
+                .help("Write the hours with <DIGITS> decimal places, e.g., 7.50 for 2."),
+            Arg::new("trim-trailing-zeros")
+                .long("trim-trailing-zeros")
+                .action(ArgAction::SetTrue)
+                .help("Write the hours without the trailing zeros, e.g., 7.5 and 3 for 7.50 and 3.00."),
+            Arg::new("round-to")
+                .long("round-to")
+                .value_name("MINUTES")
+                .value_parser(clap::value_parser!(i64).range(1..))
+                .num_args(1)
+                .help("Round the logged time to a multiple of <MINUTES>."),
+            Arg::new("round-mode")
+                .long("round-mode")
+                .alias("rounding")
+                .value_name("MODE")
+                .value_parser(clap::value_parser!(RoundMode))
+                .default_value("nearest")
+                .requires("round-to")
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Set how the time is rounded with --round-to.",
+                        "Unlike 'nearest', 'nearest-even' rounds exact halves to an even",
+                        "number of increments to avoid a systematic upward bias.",
+                    ]
+                    .join(" "),
+                )),
             Arg::new("period")
                 .long("timeframe")
                 .alias("period")
@@ -144,10 +811,57 @@ fn cli() -> clap::Command {
                 .value_parser(clap::value_parser!(Period))
                 .num_args(1)
                 .help("Set the period for filtering events."),
+            Arg::new("cap-at-now")
+                .long("cap-at-now")
+                .requires("period")
+                .action(ArgAction::SetTrue)
+                .help("Do not include events after the current moment of the period."),
+            Arg::new("date-format")
+                .long("date-format")
+                .value_name("FORMAT")
+                .value_parser(date_format_value)
+                .default_value("%Y-%m-%d")
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Write the Date column in this strftime format;",
+                        "Harvest also accepts '%-m/%-d/%Y'.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("timezone")
+                .long("timezone")
+                .value_name("IANA")
+                .value_parser(timezone_value)
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Derive the Date column and the day boundaries, e.g., of --start-date,",
+                        "in this timezone, e.g., Europe/Berlin, instead of the system timezone.",
+                        "Floating times are in this timezone, too.",
+                        "Defaults to $CALVEST_TIMEZONE.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("only-past")
+                .long("only-past")
+                .conflicts_with("only-future")
+                .action(ArgAction::SetTrue)
+                .help("Include only the events that started before now."),
+            Arg::new("only-future")
+                .long("only-future")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "Include only the events that start from now.",
+                        "Requires --end-date or --timeframe.",
+                    ]
+                    .join(" "),
+                )),
             Arg::new("start-date")
                 .long("start-date")
                 .value_name("START_DATE")
-                .value_parser(date_str_to_datetime)
+                .value_parser(date_value)
                 .num_args(1)
                 .help(wrap_help(
                     [
@@ -159,7 +873,7 @@ fn cli() -> clap::Command {
             Arg::new("end-date")
                 .long("end-date")
                 .value_name("END_DATE")
-                .value_parser(date_str_to_datetime)
+                .value_parser(date_value)
                 .num_args(1)
                 .help(wrap_help(
                     [
@@ -168,16 +882,33 @@ fn cli() -> clap::Command {
                     ]
                     .join(" "),
                 )),
+            Arg::new("include-tentative")
+                .long("include-tentative")
+                .action(ArgAction::SetTrue)
+                .help("Consider the tentatively accepted events accepted by a --required-attendee."),
+            Arg::new("my-email")
+                .long("my-email")
+                .value_name("ADDRESS")
+                .value_parser(NonEmptyStringValueParser::new())
+                .num_args(1)
+                .help("Skip the events that the attendee with this email address declined."),
             Arg::new("required-attendee")
                 .long("required-attendee")
                 .value_name("ATTENDEE")
                 .value_parser(NonEmptyStringValueParser::new())
                 .action(ArgAction::Set)
                 .num_args(1)
-                .help("Require this attendee to accept the event."),
+                .help(wrap_help(
+                    [
+                        "Require this attendee to accept the event.",
+                        "Matches the email address, with or without 'mailto:', or the CN name;",
+                        "case-insensitively.",
+                    ]
+                    .join(" "),
+                )),
             Arg::new("task")
                 .long("task")
-                .value_names(&[
+                .value_names([
                     "TASK_NAME",
                     "PROJECT_NAME",
                     "PROJECT_CODE",
@@ -190,6 +921,150 @@ fn cli() -> clap::Command {
                 .help(
                     "Use these task, project, and client when the event summary matches the regex.",
                 ),
+            Arg::new("task-hours")
+                .long("task-hours")
+                .value_names(["REGEX", "HOURS"])
+                .value_parser(NonEmptyStringValueParser::new())
+                .action(ArgAction::Append)
+                .num_args(2)
+                .help(wrap_help(
+                    [
+                        "Log <HOURS> instead of the event duration when the event summary",
+                        "matches the regex. The rounding and the client billing still apply.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("task-by-category")
+                .long("task-by-category")
+                .value_names([
+                    "CATEGORY",
+                    "TASK_NAME",
+                    "PROJECT_NAME",
+                    "PROJECT_CODE",
+                    "CLIENT_NAME",
+                ])
+                .value_parser(NonEmptyStringValueParser::new())
+                .action(ArgAction::Append)
+                .num_args(5)
+                .help(wrap_help(
+                    [
+                        "Use these task, project, and client when the event has the category",
+                        "in CATEGORIES. A category match takes precedence over a --task match.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("ignore-case")
+                .long("ignore-case")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "Match the --task and --exclude-pattern regexes case-insensitively.",
+                        "A single regex can also start with '(?i)' instead.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("match-anchor")
+                .long("match-anchor")
+                .value_name("ANCHOR")
+                .value_parser(clap::value_parser!(MatchAnchor))
+                .default_value("substring")
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Set whether the --task and --exclude-pattern regexes match",
+                        "a part of the summary or the whole summary.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("notes-from")
+                .long("notes-from")
+                .value_name("PROPERTY")
+                .value_parser(clap::value_parser!(NotesSource))
+                .value_delimiter(',')
+                .action(ArgAction::Append)
+                .default_value("summary")
+                .help(wrap_help(
+                    [
+                        "Take the notes, also matched by the --task and --exclude-pattern",
+                        "regexes, from the first of these non-empty properties,",
+                        "e.g., summary,description.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("skip-decorative")
+                .long("skip-decorative")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "Exclude the events whose summary has no letters or digits,",
+                        "e.g., emoji or separator lines.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("exclude-pattern")
+                .long("exclude-pattern")
+                .value_name("REGEX")
+                .value_parser(NonEmptyStringValueParser::new())
+                .action(ArgAction::Append)
+                .num_args(1)
+                .help("Exclude the events whose summary matches the regex."),
+            Arg::new("location")
+                .long("location")
+                .value_name("REGEX")
+                .value_parser(regex_value)
+                .num_args(1)
+                .help("Include only the events whose LOCATION matches the regex."),
+            Arg::new("exclude-location")
+                .long("exclude-location")
+                .value_name("REGEX")
+                .value_parser(regex_value)
+                .num_args(1)
+                .help("Exclude the events whose LOCATION matches the regex."),
+            Arg::new("drop-notes-after")
+                .long("drop-notes-after")
+                .value_name("REGEX")
+                .value_parser(regex_value)
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Drop the notes starting from the first line matching the regex,",
+                        "e.g., a signature or a meeting link trailer.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("strip-notes-regex")
+                .long("strip-notes-regex")
+                .value_name("REGEX")
+                .value_parser(regex_value)
+                .action(ArgAction::Append)
+                .num_args(1)
+                .help("Remove the regex matches from the notes, e.g., confidential ticket ids."),
+            Arg::new("strip-notes-replacement")
+                .long("strip-notes-replacement")
+                .value_name("TEXT")
+                .requires("strip-notes-regex")
+                .default_value("")
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Replace the --strip-notes-regex matches with <TEXT>",
+                        "instead of removing them.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("notes-template")
+                .long("notes-template")
+                .value_name("TEMPLATE")
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Write the Notes column in this template;",
+                        "'{notes}', '{summary}', '{task}', '{project}', and '{client}'",
+                        "are replaced with the values of the work, e.g., '{client}: {notes}'.",
+                        "The notes-template of a config file task takes precedence.",
+                    ]
+                    .join(" "),
+                )),
             Arg::new("include-property")
                 .long("include-property")
                 .value_name("PROPERTY_NAME")
@@ -202,6 +1077,191 @@ fn cli() -> clap::Command {
                 ))
                 .action(ArgAction::Set)
                 .num_args(1),
+            Arg::new("with-organizer")
+                .long("with-organizer")
+                .action(ArgAction::SetTrue)
+                .help("Add the event organizer as the last column."),
+            Arg::new("with-minutes")
+                .long("with-minutes")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "Add the duration in whole minutes as the last column,",
+                        "before any break and rounding.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("with-summary")
+                .long("with-summary")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "Add the event SUMMARY as the last column,",
+                        "as is, without the notes transformations.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("with-status")
+                .long("with-status")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "Add the event STATUS, e.g., TENTATIVE, as the last column.",
+                        "Blank when the event has no STATUS.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("with-uid")
+                .long("with-uid")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "Add the event UID as the last column.",
+                        "An occurrence of a recurring event gets its start appended,",
+                        "e.g., <UID>_20250106T090000Z.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("name-style")
+                .long("name-style")
+                .value_name("STYLE")
+                .value_parser(clap::value_parser!(NameStyle))
+                .default_value("separate")
+                .num_args(1)
+                .help("Set how the first and the last name are written."),
+            Arg::new("name-combine-format")
+                .long("name-combine-format")
+                .value_name("FORMAT")
+                .default_value("{first} {last}")
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Format the name with --name-style combined;",
+                        "'{first}' and '{last}' are replaced with the first and the last name.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("sort")
+                .long("sort")
+                .value_name("ORDER")
+                .value_parser(clap::value_parser!(SortOrder))
+                .num_args(1)
+                .help("Sort the work entries."),
+            Arg::new("reverse")
+                .long("reverse")
+                .requires("sort")
+                .action(ArgAction::SetTrue)
+                .help("Sort the work entries newest first."),
+            Arg::new("fail-on-empty")
+                .long("fail-on-empty")
+                .action(ArgAction::SetTrue)
+                .help("Exit with an error if there are no work entries."),
+            Arg::new("allow-empty")
+                .long("allow-empty")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "Exit successfully if there are no work entries,",
+                        "even with --fail-on-empty.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("post-hook")
+                .long("post-hook")
+                .value_name("COMMAND")
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Run the shell <COMMAND> after a successful run with the --output file",
+                        "as its argument and the number of work entries and the total hours",
+                        "in the CALVEST_EVENTS and CALVEST_HOURS environment variables.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("max-rows")
+                .long("max-rows")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .num_args(1)
+                .help("Fail before writing any work entry if there are more than <N> of them."),
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .action(ArgAction::Count)
+                .help(wrap_help(
+                    [
+                        "Print debugging details to <stderr>;",
+                        "-vv prints the timezone each DTSTART is resolved in.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("verbose")
+                .help("Print only the errors to <stderr>, no progress, warnings, or summary."),
+            Arg::new("max-warnings")
+                .long("max-warnings")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .num_args(1)
+                .help(wrap_help(
+                    [
+                        "Print only the first <N> warnings",
+                        "and then the number of the suppressed ones.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("report-conflicts")
+                .long("report-conflicts")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "Warn about the overlapping events of different UIDs,",
+                        "including the occurrences of recurring events.",
+                        "All-day events are not reported.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("dry-run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .help("Print the work entries as a table instead of writing any output."),
+            Arg::new("summary-only")
+                .long("summary-only")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("dry-run")
+                .help("Print only the hours per client, project, and task without writing any output."),
+            Arg::new("suggest-tasks")
+                .long("suggest-tasks")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["dry-run", "summary-only"])
+                .help(wrap_help(
+                    [
+                        "Print the summaries that match no --task pattern",
+                        "with their counts and hours, the most time-consuming first,",
+                        "without writing any output.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("validate-harvest")
+                .long("validate-harvest")
+                .action(ArgAction::SetTrue)
+                .help(wrap_help(
+                    [
+                        "Check the work entries against the Harvest import format rules",
+                        "without writing any output, and fail if any entry violates them.",
+                    ]
+                    .join(" "),
+                )),
+            Arg::new("preview-count")
+                .long("preview-count")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .requires("dry-run")
+                .num_args(1)
+                .help("Print only the first <N> work entries with --dry-run."),
             Arg::new("print-completions")
                 .long("print-completions")
                 .value_name("SHELL")
@@ -210,9 +1270,32 @@ fn cli() -> clap::Command {
         ])
 }
 
+fn cli_error(kind: ErrorKind, message: impl std::fmt::Display) -> ! {
+    cli().error(kind, message).exit()
+}
+
+fn required<T>(value: Option<T>, name: &str) -> T {
+    value.unwrap_or_else(|| {
+        cli_error(
+            ErrorKind::MissingRequiredArgument,
+            format!("--{name} must be set either on the command line or in the config file"),
+        )
+    })
+}
+
+fn cli_or_file_strings(matches: &ArgMatches, id: &str, file_values: Vec<String>) -> Vec<String> {
+    match matches.get_many::<String>(id) {
+        Some(values) => values.cloned().collect(),
+        None => file_values,
+    }
+}
+
 pub(crate) fn config() -> Config {
-    let matches = cli().get_matches();
+    config_from(cli().get_matches(), Utc::now())
+}
 
+/// The configuration with the timeframes and --cap-at-now relative to `now`.
+pub(crate) fn config_from(matches: ArgMatches, now: DateTime<Utc>) -> Config {
     if let Some(shell) = matches.get_one::<Shell>("print-completions").copied() {
         let mut cmd = cli();
         eprintln!("Generating completion file for {shell}...");
@@ -221,76 +1304,517 @@ pub(crate) fn config() -> Config {
         std::process::exit(0);
     }
 
-    let (start_date, end_date) = match matches.get_one::<Period>("period").cloned() {
-        Some(Period::LastMonth) => {
-            let end_date = current_month_start();
-            let start_date = prev_month_start(&end_date);
-            (Some(start_date.to_utc()), Some(end_date.to_utc()))
-        }
-        Some(Period::ThisMonth) => {
-            let start_date = current_month_start();
-            let end_date = next_month_start(&start_date);
+    let timezone = matches
+        .get_one::<Tz>("timezone")
+        .copied()
+        .or_else(|| {
+            env_var("CALVEST_TIMEZONE").map(|tz| {
+                timezone_value(&tz).unwrap_or_else(|e| {
+                    cli_error(
+                        ErrorKind::ValueValidation,
+                        format!("Invalid CALVEST_TIMEZONE: {e}"),
+                    )
+                })
+            })
+        })
+        .unwrap_or_else(system_timezone);
+    let (start_date, end_date) = match matches.get_one::<Period>("period") {
+        Some(period) => {
+            let today = now.with_timezone(&timezone).date_naive();
+            let (start_date, end_date) = period.range(today, timezone);
             (Some(start_date.to_utc()), Some(end_date.to_utc()))
         }
         None => (
-            matches.get_one::<DateTime<Utc>>("start-date").cloned(),
             matches
-                .get_one::<DateTime<Utc>>("end-date")
-                .cloned()
-                .map(|d| d.checked_add_days(Days::new(1)).unwrap_or(d).to_utc()),
+                .get_one::<NaiveDate>("start-date")
+                .map(|date| midnight(*date, timezone).to_utc()),
+            matches
+                .get_one::<NaiveDate>("end-date")
+                .map(|date| midnight(*date + Days::new(1), timezone).to_utc()),
         ),
     };
+    let end_date = if matches.get_flag("cap-at-now") {
+        end_date.map(|d| d.min(now))
+    } else {
+        end_date
+    };
+
+    if matches.get_flag("only-future") && end_date.is_none() {
+        cli_error(
+            ErrorKind::MissingRequiredArgument,
+            "--only-future requires --end-date or --timeframe",
+        );
+    }
+
+    let file = match matches.get_one::<PathBuf>("config") {
+        Some(path) => ConfigFile::load(path).unwrap_or_else(|e| cli_error(ErrorKind::Io, e)),
+        None => ConfigFile::default(),
+    };
+    let first_name = matches
+        .get_one::<String>("first-name")
+        .cloned()
+        .or_else(|| env_var("CALVEST_FIRST_NAME"));
+    let last_name = matches
+        .get_one::<String>("last-name")
+        .cloned()
+        .or_else(|| env_var("CALVEST_LAST_NAME"));
+    let default_task = matches
+        .get_many::<String>("default-task")
+        .map(|c| c.cloned().collect::<Vec<_>>())
+        .or_else(|| {
+            env_var("CALVEST_DEFAULT_TASK").map(|value| {
+                let c = value
+                    .split(';')
+                    .map(|c| c.trim().to_string())
+                    .collect::<Vec<_>>();
+                if c.len() != 4 || c.iter().any(String::is_empty) {
+                    cli_error(
+                        ErrorKind::ValueValidation,
+                        format!(
+                            "Invalid CALVEST_DEFAULT_TASK '{value}', expected \
+                             TASK_NAME;PROJECT_NAME;PROJECT_CODE;CLIENT_NAME"
+                        ),
+                    );
+                }
+                c
+            })
+        })
+        .map(|c| Task {
+            name: c[0].clone(),
+            project: c[1].clone(),
+            project_code: c[2].clone(),
+            client: c[3].clone(),
+        });
+    let ignore_case = matches.get_flag("ignore-case");
+    let anchor = *matches.get_one::<MatchAnchor>("match-anchor").unwrap();
+    let file_tasks = file
+        .tasks
+        .into_iter()
+        .map(|entry| {
+            let regex = pattern_regex(&entry.regex, ignore_case, anchor).unwrap_or_else(|e| {
+                cli_error(
+                    ErrorKind::ValueValidation,
+                    format!(
+                        "Invalid task regex '{}' in the config file: {e}",
+                        entry.regex
+                    ),
+                )
+            });
+            if entry.round_to.is_some_and(|increment| increment < 1) {
+                cli_error(
+                    ErrorKind::ValueValidation,
+                    format!(
+                        "Invalid round-to of the task '{}' in the config file: must be at least 1",
+                        entry.name
+                    ),
+                );
+            }
+            let rounding = entry
+                .round_to
+                .map(|increment| (increment, entry.round_mode.unwrap_or(RoundMode::Nearest)));
+            let task = Task {
+                name: entry.name,
+                project: entry.project,
+                project_code: entry.project_code,
+                client: entry.client,
+            };
+            TaskPattern {
+                task,
+                regex,
+                rounding,
+                hours: entry.hours,
+                notes_template: entry.notes_template,
+                billable: entry.billable,
+            }
+        })
+        .collect::<Vec<_>>();
+    let non_empty = |name: Option<String>| name.filter(|name| !name.is_empty());
 
     let config = Config {
-        output: matches.get_one::<PathBuf>("output").map(Clone::clone),
-        input: matches.get_one::<PathBuf>("input").map(Clone::clone),
-        extra_props: matches
-            .get_many::<String>("include-property")
+        output: matches.get_one::<PathBuf>("output").cloned(),
+        input: matches
+            .get_many::<PathBuf>("input")
+            .map(|paths| paths.cloned().collect())
+            .unwrap_or_default(),
+        input_encoding: *matches.get_one::<InputEncoding>("input-encoding").unwrap(),
+        headers: matches
+            .get_many::<(String, String)>("header")
             .unwrap_or_default()
-            .map(Clone::clone)
+            .cloned()
             .collect(),
-        first_name: matches.get_one::<String>("first-name").unwrap().clone(),
-        last_name: matches.get_one::<String>("last-name").unwrap().clone(),
-        default_task: matches
-            .get_many::<String>("default-task")
+        http_timeout: *matches.get_one::<u64>("http-timeout").unwrap(),
+        lenient_input: matches.get_flag("lenient-input"),
+        format: *matches.get_one::<OutputFormat>("format").unwrap(),
+        also_json: matches.get_one::<PathBuf>("also-json").cloned(),
+        also_jsonl: matches.get_one::<PathBuf>("also-jsonl").cloned(),
+        split_by_month: matches.get_one::<PathBuf>("split-by-month").cloned(),
+        extra_props: cli_or_file_strings(&matches, "include-property", file.include_properties),
+        first_name: required(non_empty(first_name.or(file.first_name)), "first-name"),
+        last_name: required(non_empty(last_name.or(file.last_name)), "last-name"),
+        default_task: required(
+            default_task.or(file.default_task.map(Task::from)),
+            "default-task",
+        ),
+        all_day_hours: *matches.get_one::<f64>("all-day-hours").unwrap(),
+        default_hours: matches.get_one::<f64>("default-hours").copied(),
+        ignore_rrule: matches.get_flag("ignore-rrule"),
+        infer_end_from_next: matches.get_flag("infer-end-from-next"),
+        max_inferred_hours: *matches.get_one::<f64>("max-inferred-hours").unwrap(),
+        min_duration: matches.get_one::<i64>("min-duration").copied(),
+        break_rule: matches.get_one::<BreakRule>("subtract-break-over").copied(),
+        split_multiday: matches.get_flag("split-multiday"),
+        clamp_to_day: matches.get_flag("clamp-to-day"),
+        normalize_day: matches.get_one::<f64>("normalize-day").copied(),
+        client_billing: matches
+            .get_many::<String>("client-billing")
+            .unwrap_or_default()
+            .collect::<Vec<&String>>()
+            .chunks(3)
             .map(|c| {
-                let c = c.collect::<Vec<_>>();
-                Task {
-                    name: c[0].clone(),
-                    project: c[1].clone(),
-                    project_code: c[2].clone(),
-                    client: c[3].clone(),
+                let minutes = |value: &str| {
+                    value.parse::<i64>().ok().filter(|m| *m > 0).unwrap_or_else(|| {
+                        cli_error(
+                            ErrorKind::InvalidValue,
+                            format!(
+                                "--client-billing '{}': '{value}' is not a positive number of minutes",
+                                c[0]
+                            ),
+                        )
+                    })
+                };
+                ClientBilling {
+                    client: c[0].clone(),
+                    increment: minutes(c[1]),
+                    minimum: minutes(c[2]),
                 }
             })
-            .unwrap(),
+            .collect(),
+        dedup_overlaps: matches
+            .get_one::<OverlapStrategy>("dedup-overlaps")
+            .copied(),
+        merge_gap: matches.get_one::<i64>("merge-gap").copied(),
+        merge_when_notes_match: matches.get_one::<Regex>("merge-when-notes-match").cloned(),
+        unique_notes: matches.get_flag("unique-notes"),
+        aggregate_daily: matches.get_flag("aggregate-daily"),
+        round_to: matches.get_one::<i64>("round-to").copied(),
+        round_mode: *matches.get_one::<RoundMode>("round-mode").unwrap(),
+        timezone,
+        hours_format: HoursFormat {
+            precision: *matches.get_one::<u8>("hours-precision").unwrap() as usize,
+            trim_trailing_zeros: matches.get_flag("trim-trailing-zeros"),
+        },
         start_date,
         end_date,
-        tasks: matches
-            .get_many::<String>("task")
+        date_format: matches.get_one::<String>("date-format").unwrap().clone(),
+        only_past: matches.get_flag("only-past"),
+        only_future: matches.get_flag("only-future"),
+        tasks: match matches.get_many::<String>("task") {
+            None => file_tasks,
+            Some(tasks) => tasks
+                .collect::<Vec<&String>>()
+                .chunks(5)
+                .map(|c| {
+                    let task = Task {
+                        name: c[0].clone(),
+                        project: c[1].clone(),
+                        project_code: c[2].clone(),
+                        client: c[3].clone(),
+                    };
+                    let regex = pattern_regex(c[4], ignore_case, anchor).unwrap_or_else(|e| {
+                        cli_error(
+                            ErrorKind::ValueValidation,
+                            format!("Invalid --task regex '{}': {e}", c[4]),
+                        )
+                    });
+                    TaskPattern {
+                        task,
+                        regex,
+                        rounding: None,
+                        hours: None,
+                        notes_template: None,
+                        billable: true,
+                    }
+                })
+                .collect(),
+        },
+        task_hours: matches
+            .get_many::<String>("task-hours")
             .unwrap_or_default()
-            .into_iter()
             .collect::<Vec<&String>>()
-            .chunks(5)
+            .chunks(2)
             .map(|c| {
-                let task = Task {
-                    name: c[0].clone(),
-                    project: c[1].clone(),
-                    project_code: c[2].clone(),
-                    client: c[3].clone(),
-                };
-                TaskPattern {
-                    task,
-                    // TODO parse during the config parsing
-                    regex: Regex::new(c[4]).unwrap(),
-                }
+                let regex = pattern_regex(c[0], ignore_case, anchor).unwrap_or_else(|e| {
+                    cli_error(
+                        ErrorKind::ValueValidation,
+                        format!("Invalid --task-hours regex '{}': {e}", c[0]),
+                    )
+                });
+                let hours = c[1]
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|hours| *hours >= 0.0)
+                    .unwrap_or_else(|| {
+                        cli_error(
+                            ErrorKind::ValueValidation,
+                            format!("Invalid --task-hours hours '{}': expected e.g. 0.5", c[1]),
+                        )
+                    });
+                (regex, hours)
+            })
+            .collect(),
+        category_tasks: matches
+            .get_many::<String>("task-by-category")
+            .unwrap_or_default()
+            .collect::<Vec<&String>>()
+            .chunks(5)
+            .map(|c| CategoryTask {
+                category: c[0].clone(),
+                task: Task {
+                    name: c[1].clone(),
+                    project: c[2].clone(),
+                    project_code: c[3].clone(),
+                    client: c[4].clone(),
+                },
+            })
+            .collect(),
+        notes_from: matches
+            .get_many::<NotesSource>("notes-from")
+            .unwrap()
+            .copied()
+            .collect(),
+        skip_decorative: matches.get_flag("skip-decorative"),
+        exclude_patterns: matches
+            .get_many::<String>("exclude-pattern")
+            .unwrap_or_default()
+            .map(|pattern| {
+                pattern_regex(pattern, ignore_case, anchor).unwrap_or_else(|e| {
+                    cli_error(
+                        ErrorKind::ValueValidation,
+                        format!("Invalid --exclude-pattern '{pattern}': {e}"),
+                    )
+                })
             })
             .collect(),
-        required_attendies: matches
-            .get_many::<String>("required-attendee")
+        required_attendies: cli_or_file_strings(
+            &matches,
+            "required-attendee",
+            file.required_attendees,
+        )
+        .into_iter()
+        .map(|attendee| cal_address(&attendee))
+        .collect(),
+        accepted_states: if matches.get_flag("include-tentative") {
+            vec!["ACCEPTED", "TENTATIVE"]
+        } else {
+            vec!["ACCEPTED"]
+        },
+        my_email: matches
+            .get_one::<String>("my-email")
+            .map(|address| cal_address(address)),
+        location: matches.get_one::<Regex>("location").cloned(),
+        exclude_location: matches.get_one::<Regex>("exclude-location").cloned(),
+        drop_notes_after: matches.get_one::<Regex>("drop-notes-after").cloned(),
+        strip_notes: matches
+            .get_many::<Regex>("strip-notes-regex")
             .unwrap_or_default()
-            .into_iter()
-            .map(Clone::clone)
+            .cloned()
             .collect(),
+        notes_template: matches.get_one::<String>("notes-template").cloned(),
+        strip_notes_replacement: matches
+            .get_one::<String>("strip-notes-replacement")
+            .unwrap()
+            .clone(),
+        with_organizer: matches.get_flag("with-organizer"),
+        with_uid: matches.get_flag("with-uid"),
+        with_minutes: matches.get_flag("with-minutes"),
+        with_summary: matches.get_flag("with-summary"),
+        with_status: matches.get_flag("with-status"),
+        name_style: *matches.get_one::<NameStyle>("name-style").unwrap(),
+        name_combine_format: matches
+            .get_one::<String>("name-combine-format")
+            .unwrap()
+            .clone(),
+        sort: matches.get_one::<SortOrder>("sort").cloned(),
+        reverse: matches.get_flag("reverse"),
+        fail_on_empty: matches.get_flag("fail-on-empty") && !matches.get_flag("allow-empty"),
+        post_hook: matches.get_one::<String>("post-hook").cloned(),
+        max_rows: matches.get_one::<usize>("max-rows").copied(),
+        verbosity: matches.get_count("verbose"),
+        quiet: matches.get_flag("quiet"),
+        max_warnings: matches.get_one::<usize>("max-warnings").copied(),
+        report_conflicts: matches.get_flag("report-conflicts"),
+        dry_run: matches.get_flag("dry-run"),
+        summary_only: matches.get_flag("summary-only"),
+        suggest_tasks: matches.get_flag("suggest-tasks"),
+        validate_harvest: matches.get_flag("validate-harvest"),
+        preview_count: matches.get_one::<usize>("preview-count").copied(),
     };
+    let column_names = config.column_names();
+    if let Some(prop) = config.extra_props.iter().find(|prop| {
+        column_names
+            .iter()
+            .any(|column| column.eq_ignore_ascii_case(prop))
+    }) {
+        cli_error(
+            ErrorKind::ArgumentConflict,
+            format!("The included property '{prop}' collides with a column name"),
+        );
+    }
     config
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config(args: &[&str]) -> Config {
+        test_config_at(args, Utc::now())
+    }
+
+    fn test_config_at(args: &[&str], now: DateTime<Utc>) -> Config {
+        let args = ["calvest"].iter().chain(args);
+        config_from(cli().try_get_matches_from(args).unwrap(), now)
+    }
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[test]
+    fn config_file() {
+        let config = test_config(&["--config", &fixture("config.toml")]);
+        assert_eq!(config.first_name, "Ada");
+        assert_eq!(config.last_name, "Lovelace");
+        assert_eq!(config.default_task.name, "Development");
+        assert_eq!(config.default_task.project, "Engine");
+        assert_eq!(config.default_task.project_code, "EN");
+        assert_eq!(config.default_task.client, "Babbage");
+        assert_eq!(config.tasks.len(), 1);
+        assert_eq!(config.tasks[0].task.name, "Review");
+        assert!(config.tasks[0].regex.is_match("Code review"));
+        assert!(config.tasks[0].billable);
+        assert_eq!(config.tasks[0].hours, Some(0.5));
+        assert_eq!(config.extra_props, ["LOCATION"]);
+        assert!(config.required_attendies.contains("ada@example.com"));
+    }
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let config = test_config(&[
+            "--config",
+            &fixture("config.toml"),
+            "--first-name",
+            "Charles",
+            "--task",
+            "Design",
+            "Engine",
+            "EN",
+            "Babbage",
+            "design",
+        ]);
+        assert_eq!(config.first_name, "Charles");
+        assert_eq!(config.last_name, "Lovelace");
+        assert_eq!(config.tasks.len(), 1);
+        assert_eq!(config.tasks[0].task.name, "Design");
+    }
+
+    #[test]
+    fn column_names_follow_the_flags() {
+        let config = test_config(&["--config", &fixture("config.toml")]);
+        assert_eq!(config.column_names(), REQUIRED_CSV_COLUMN_NAMES);
+        let config = test_config(&[
+            "--config",
+            &fixture("config.toml"),
+            "--name-style",
+            "combined",
+            "--with-uid",
+            "--with-status",
+        ]);
+        let column_names = config.column_names();
+        assert!(!column_names.contains(&"First name"));
+        assert!(!column_names.contains(&"Last name"));
+        assert!(column_names.ends_with(&["Name", "UID", "Status"]));
+    }
+
+    fn range(period: Period, today: &str, tz: Tz) -> (String, String) {
+        let today = date_value(today).unwrap();
+        let (start_date, end_date) = period.range(today, tz);
+        (start_date.to_rfc3339(), end_date.to_rfc3339())
+    }
+
+    fn pair(start: &str, end: &str) -> (String, String) {
+        (start.to_string(), end.to_string())
+    }
+
+    #[test]
+    fn periods() {
+        // A Wednesday.
+        let today = "2025-03-05";
+        assert_eq!(
+            range(Period::ThisWeek, today, Tz::UTC),
+            pair("2025-03-03T00:00:00+00:00", "2025-03-10T00:00:00+00:00")
+        );
+        assert_eq!(
+            range(Period::LastWeek, today, Tz::UTC),
+            pair("2025-02-24T00:00:00+00:00", "2025-03-03T00:00:00+00:00")
+        );
+        assert_eq!(
+            range(Period::ThisMonth, today, Tz::UTC),
+            pair("2025-03-01T00:00:00+00:00", "2025-04-01T00:00:00+00:00")
+        );
+        assert_eq!(
+            range(Period::LastMonth, today, Tz::UTC),
+            pair("2025-02-01T00:00:00+00:00", "2025-03-01T00:00:00+00:00")
+        );
+        assert_eq!(
+            range(Period::YearToDate, today, Tz::UTC),
+            pair("2025-01-01T00:00:00+00:00", "2025-03-06T00:00:00+00:00")
+        );
+    }
+
+    #[test]
+    fn cap_at_now_excludes_the_rest_of_the_period() {
+        let now = DateTime::parse_from_rfc3339("2025-03-05T12:00:00Z")
+            .unwrap()
+            .to_utc();
+        let later_this_month = DateTime::parse_from_rfc3339("2025-03-20T09:00:00Z")
+            .unwrap()
+            .to_utc();
+        let args = [
+            "--config",
+            &fixture("config.toml"),
+            "--timezone",
+            "UTC",
+            "--timeframe",
+            "this-month",
+        ];
+        let config = test_config_at(&args, now);
+        assert!(config.end_date.unwrap() > later_this_month);
+        let config = test_config_at(&[&args[..], &["--cap-at-now"]].concat(), now);
+        assert_eq!(
+            config.start_date.unwrap().to_rfc3339(),
+            "2025-03-01T00:00:00+00:00"
+        );
+        assert_eq!(config.end_date, Some(now));
+        assert!(config.end_date.unwrap() <= later_this_month);
+    }
+
+    #[test]
+    fn periods_across_the_year_boundary() {
+        // A Thursday, with the week starting in 2024.
+        let today = "2025-01-02";
+        let tz = Tz::Europe__Berlin;
+        assert_eq!(
+            range(Period::ThisWeek, today, tz),
+            pair("2024-12-30T00:00:00+01:00", "2025-01-06T00:00:00+01:00")
+        );
+        assert_eq!(
+            range(Period::LastWeek, today, tz),
+            pair("2024-12-23T00:00:00+01:00", "2024-12-30T00:00:00+01:00")
+        );
+        assert_eq!(
+            range(Period::YearToDate, today, tz),
+            pair("2025-01-01T00:00:00+01:00", "2025-01-03T00:00:00+01:00")
+        );
+    }
+}
@@ -1,5 +1,8 @@
 use chrono::DateTime;
+use chrono::Duration;
+use chrono::NaiveDate;
 use chrono::Utc;
+use chrono_tz::Tz;
 
 /// Date (YYYY-MM-DD or M/D/YYYY formats; for example: 2023-08-25 or 8/25/2023)
 /// Hours (In decimal format, without any stray characters; for example: 7.5, 3, 9.9)
@@ -15,6 +18,111 @@ pub(crate) const REQUIRED_CSV_COLUMN_NAMES: &[&str] = &[
     "Last name",
 ];
 
+/// The columns the Harvest importer accepts empty.
+const OPTIONAL_CSV_COLUMN_NAMES: &[&str] = &["Project Code", "Notes"];
+
+/// The violations of the Harvest import format rules by the record.
+pub(crate) fn validate_record(column_names: &[&str], record: &[&str]) -> Vec<String> {
+    let mut violations = vec![];
+    for (column, value) in column_names.iter().zip(record) {
+        if !REQUIRED_CSV_COLUMN_NAMES.contains(column) {
+            continue;
+        }
+        if value.trim().is_empty() {
+            if !OPTIONAL_CSV_COLUMN_NAMES.contains(column) {
+                violations.push(format!("{column} is empty"));
+            }
+            continue;
+        }
+        match *column {
+            "Date"
+                if ["%Y-%m-%d", "%m/%d/%Y"]
+                    .iter()
+                    .all(|format| NaiveDate::parse_from_str(value, format).is_err()) =>
+            {
+                violations.push(format!("Date '{value}' is neither YYYY-MM-DD nor M/D/YYYY"));
+            }
+            "Hours"
+                if !value.chars().all(|c| c.is_ascii_digit() || c == '.')
+                    || value.parse::<f64>().is_err() =>
+            {
+                violations.push(format!("Hours '{value}' is not a decimal number"));
+            }
+            _ => {}
+        }
+    }
+    violations
+}
+
+/// How the logged minutes are rounded to the rounding increment.
+#[derive(clap::ValueEnum, serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum RoundMode {
+    /// To the nearest increment; halves round up.
+    Nearest,
+    /// To the nearest increment; halves round to an even number of increments.
+    NearestEven,
+    Up,
+    Down,
+}
+
+impl RoundMode {
+    pub(crate) fn round(self, minutes: i64, increment: i64) -> i64 {
+        let (n, rest) = (minutes.div_euclid(increment), minutes.rem_euclid(increment));
+        let n = match self {
+            Self::Down => n,
+            Self::Up if rest > 0 => n + 1,
+            Self::Up => n,
+            Self::Nearest if 2 * rest >= increment => n + 1,
+            Self::Nearest => n,
+            Self::NearestEven if 2 * rest > increment || (2 * rest == increment && n % 2 == 1) => {
+                n + 1
+            }
+            Self::NearestEven => n,
+        };
+        n * increment
+    }
+}
+
+/// An unbilled break subtracted from the works longer than `over_minutes`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BreakRule {
+    pub(crate) over_minutes: i64,
+    pub(crate) minutes: i64,
+}
+
+impl BreakRule {
+    pub(crate) fn apply(self, minutes: i64) -> i64 {
+        if minutes > self.over_minutes {
+            (minutes - self.minutes).max(0)
+        } else {
+            minutes
+        }
+    }
+}
+
+/// How the decimal hours are written.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HoursFormat {
+    pub(crate) precision: usize,
+    /// Whether to write, e.g., `7.50` as `7.5` and `3.00` as `3`.
+    pub(crate) trim_trailing_zeros: bool,
+}
+
+impl HoursFormat {
+    pub(crate) fn format(self, hours: f64) -> String {
+        let hours = format!("{hours:.precision$}", precision = self.precision);
+        if self.trim_trailing_zeros && hours.contains('.') {
+            hours
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_string()
+        } else {
+            hours
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct Task {
     pub(crate) name: String,
@@ -27,6 +135,8 @@ pub(crate) struct Task {
 pub(crate) struct Work {
     pub(crate) start_datetime: Option<DateTime<Utc>>,
     pub(crate) end_datetime: Option<DateTime<Utc>>,
+    /// Overrides the duration between the start and the end datetime.
+    pub(crate) duration: Option<Duration>,
     pub(crate) notes: Option<String>,
     pub(crate) first_name: String,
     pub(crate) last_name: String,
@@ -38,6 +148,7 @@ impl Work {
         Self {
             start_datetime: None,
             end_datetime: None,
+            duration: None,
             notes: None,
             first_name,
             last_name,
@@ -45,18 +156,174 @@ impl Work {
         }
     }
 
-    pub(crate) fn hours(&self) -> Option<String> {
-        let end_datetime = self.end_datetime.as_ref()?;
-        let start_datetime = self.start_datetime.as_ref()?;
-        let duration = end_datetime.clone().signed_duration_since(start_datetime);
-        let minutes = duration.num_minutes();
-        let hours = minutes as f64 / 60.0;
-        Some(format!("{hours:.2}"))
+    pub(crate) fn minutes(&self) -> Option<i64> {
+        let duration = match self.duration {
+            Some(duration) => duration,
+            None => {
+                let end_datetime = self.end_datetime.as_ref()?;
+                let start_datetime = self.start_datetime.as_ref()?;
+                end_datetime.signed_duration_since(*start_datetime)
+            }
+        };
+        Some(duration.num_minutes())
+    }
+
+    /// The hours net of the `break_rule` break if any, rounded to the
+    /// `rounding` increment in minutes if any, and at least `minimum` minutes
+    /// if any, written in the `format`.
+    pub(crate) fn hours(
+        &self,
+        break_rule: Option<BreakRule>,
+        rounding: Option<(i64, RoundMode)>,
+        minimum: Option<i64>,
+        format: HoursFormat,
+    ) -> Option<String> {
+        let minutes = self.minutes()?;
+        let minutes = match break_rule {
+            Some(break_rule) => break_rule.apply(minutes),
+            None => minutes,
+        };
+        let minutes = match rounding {
+            Some((increment, mode)) => mode.round(minutes, increment),
+            None => minutes,
+        };
+        let minutes = match minimum {
+            Some(minimum) => minutes.max(minimum),
+            None => minutes,
+        };
+        Some(format.format(minutes as f64 / 60.0))
+    }
+
+    /// The start date in the `tz` timezone in the strftime `format`.
+    pub(crate) fn date_string(&self, format: &str, tz: Tz) -> Option<String> {
+        self.start_datetime.as_ref().map(|dt| {
+            dt.with_timezone(&tz)
+                .date_naive()
+                .format(format)
+                .to_string()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOURS: HoursFormat = HoursFormat {
+        precision: 2,
+        trim_trailing_zeros: false,
+    };
+
+    fn work(minutes: i64) -> Work {
+        let task = Task {
+            name: "Development".to_string(),
+            project: "Engine".to_string(),
+            project_code: "EN".to_string(),
+            client: "Babbage".to_string(),
+        };
+        let mut work = Work::new("Ada".to_string(), "Lovelace".to_string(), task);
+        let start_datetime = DateTime::from_timestamp(1_736_154_000, 0).unwrap();
+        work.start_datetime = Some(start_datetime);
+        work.end_datetime = Some(start_datetime + Duration::minutes(minutes));
+        work
+    }
+
+    fn rounded(minutes: i64, increment: i64, mode: RoundMode) -> String {
+        work(minutes)
+            .hours(None, Some((increment, mode)), None, HOURS)
+            .unwrap()
+    }
+
+    #[test]
+    fn no_rounding_by_default() {
+        assert_eq!(work(50).hours(None, None, None, HOURS).unwrap(), "0.83");
+    }
+
+    #[test]
+    fn rounding_to_quarter_hours() {
+        assert_eq!(rounded(50, 15, RoundMode::Up), "1.00");
+        assert_eq!(rounded(7, 15, RoundMode::Up), "0.25");
+        assert_eq!(rounded(7, 15, RoundMode::Nearest), "0.00");
+        assert_eq!(rounded(8, 15, RoundMode::Nearest), "0.25");
+        assert_eq!(rounded(50, 15, RoundMode::Down), "0.75");
+        assert_eq!(rounded(60, 15, RoundMode::Up), "1.00");
+    }
+
+    #[test]
+    fn client_minimum_bills_at_least_the_minimum() {
+        let hours = |minutes: i64| work(minutes).hours(None, None, Some(15), HOURS).unwrap();
+        assert_eq!(hours(5), "0.25");
+        assert_eq!(hours(15), "0.25");
+        assert_eq!(hours(20), "0.33");
+        // The minimum applies after the rounding.
+        let rounded = work(5).hours(None, Some((30, RoundMode::Down)), Some(15), HOURS);
+        assert_eq!(rounded.unwrap(), "0.25");
+    }
+
+    #[test]
+    fn nearest_even_rounds_halves_to_even_increments() {
+        assert_eq!(RoundMode::NearestEven.round(15, 30), 0);
+        assert_eq!(RoundMode::NearestEven.round(45, 30), 60);
+        assert_eq!(RoundMode::NearestEven.round(75, 30), 60);
+        assert_eq!(RoundMode::NearestEven.round(16, 30), 30);
+        assert_eq!(RoundMode::NearestEven.round(74, 30), 60);
+        assert_eq!(RoundMode::Nearest.round(15, 30), 30);
+        assert_eq!(RoundMode::Nearest.round(75, 30), 90);
+    }
+
+    #[test]
+    fn break_rule() {
+        let break_rule = BreakRule {
+            over_minutes: 360,
+            minutes: 30,
+        };
+        assert_eq!(break_rule.apply(360), 360);
+        assert_eq!(break_rule.apply(361), 331);
+        assert_eq!(break_rule.apply(480), 450);
+        let hours = work(480).hours(Some(break_rule), None, None, HOURS);
+        assert_eq!(hours.unwrap(), "7.50");
+    }
+
+    #[test]
+    fn break_rule_precedes_rounding() {
+        let break_rule = BreakRule {
+            over_minutes: 60,
+            minutes: 10,
+        };
+        // 70 - 10 = 60 minutes, not 75 - 10 = 65 rounded up to 75.
+        let hours = work(70).hours(Some(break_rule), Some((15, RoundMode::Up)), None, HOURS);
+        assert_eq!(hours.unwrap(), "1.00");
+        // A break longer than the work leaves nothing.
+        let break_rule = BreakRule {
+            over_minutes: 0,
+            minutes: 90,
+        };
+        assert_eq!(break_rule.apply(60), 0);
+    }
+
+    #[test]
+    fn hours_precision() {
+        let format = |precision| HoursFormat {
+            precision,
+            trim_trailing_zeros: false,
+        };
+        assert_eq!(format(1).format(50.0 / 60.0), "0.8");
+        assert_eq!(format(2).format(50.0 / 60.0), "0.83");
+        assert_eq!(format(3).format(50.0 / 60.0), "0.833");
+        assert_eq!(format(2).format(3.0), "3.00");
     }
 
-    pub(crate) fn date_string(&self) -> Option<String> {
-        self.start_datetime
-            .as_ref()
-            .map(|dt| dt.date_naive().to_string())
+    #[test]
+    fn hours_trailing_zeros_trimmed() {
+        let format = |precision| HoursFormat {
+            precision,
+            trim_trailing_zeros: true,
+        };
+        assert_eq!(format(2).format(3.0), "3");
+        assert_eq!(format(2).format(7.5), "7.5");
+        assert_eq!(format(2).format(50.0 / 60.0), "0.83");
+        assert_eq!(format(3).format(0.25), "0.25");
+        // Without a fraction, the zeros are not trailing.
+        assert_eq!(format(0).format(30.0), "30");
     }
 }
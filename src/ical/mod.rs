@@ -1,5 +1,10 @@
-mod parse;
+pub mod parse;
 mod rrule;
+mod tz;
+
+pub use rrule::EventFrequency;
+pub use rrule::RRule;
+pub use tz::TimeZones;
 
 use anyhow::anyhow;
 use anyhow::Result;
@@ -9,28 +14,90 @@ use chrono::Months;
 use chrono::Utc;
 use ical::{parser::ical::component::IcalEvent, property::Property as IcalProperty};
 
-use rrule::EventFrequency;
-use rrule::RRule;
+use parse::DstAdjustment;
+use parse::ZoneSource;
+
+/// The default upper bound for the number of occurrences produced by an
+/// [`EventIter`]. Protects against RRULEs with neither UNTIL nor COUNT.
+pub const DEFAULT_MAX_OCCURRENCES: u32 = 10_000;
 
+/// Iterates over the occurrences of an event, DTSTART first.
+///
+/// As RFC 5545 requires, DTSTART is always the first occurrence, even when
+/// it does not match the BY* rules of the RRULE, and it counts toward COUNT.
+///
+/// ```
+/// use calvest::Event;
+/// use ical::IcalParser;
+///
+/// // Tuesday DTSTART, repeating on Mondays.
+/// let ics = "BEGIN:VCALENDAR\r
+/// BEGIN:VEVENT\r
+/// UID:review\r
+/// SUMMARY:Review\r
+/// DTSTART:20250107T090000Z\r
+/// DTEND:20250107T100000Z\r
+/// RRULE:FREQ=WEEKLY;BYDAY=MO;COUNT=3\r
+/// END:VEVENT\r
+/// END:VCALENDAR\r
+/// ";
+/// let calendar = IcalParser::new(ics.as_bytes()).next().unwrap().unwrap();
+/// let event = Event::try_from(calendar.events[0].clone()).unwrap();
+/// let starts = event
+///     .recurring()
+///     .map(|event| event.start_dt.format("%Y-%m-%d").to_string())
+///     .collect::<Vec<_>>();
+/// assert_eq!(starts, ["2025-01-07", "2025-01-13", "2025-01-20"]);
+/// ```
 pub struct EventIter {
     original_event: Event,
     last_start_dt: DateTime<Utc>,
     count: u32,
+    max_occurrences: u32,
+    end_dt: Option<DateTime<Utc>>,
+    truncated: bool,
 }
 
 impl From<Event> for EventIter {
     fn from(event: Event) -> Self {
-        let last_start_dt = event.start_dt.clone();
+        let last_start_dt = event.start_dt;
         Self {
             original_event: event,
             last_start_dt,
             count: 0,
+            max_occurrences: DEFAULT_MAX_OCCURRENCES,
+            end_dt: None,
+            truncated: false,
         }
     }
 }
 
 impl EventIter {
-    /// Cannot be BYMONTHDAY, BYYEARDAY, BYWEEKNO.
+    /// Stops the iteration after `max_occurrences` events have been produced.
+    pub fn max_occurrences(mut self, max_occurrences: u32) -> Self {
+        self.max_occurrences = max_occurrences;
+        self
+    }
+
+    /// Whether `max_occurrences` stopped the iteration before the RRULE and
+    /// `end_dt` did.
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Stops the iteration at the first occurrence starting at or after `end_dt`.
+    pub fn until(mut self, end_dt: DateTime<Utc>) -> Self {
+        self.end_dt = Some(end_dt);
+        self
+    }
+
+    fn is_past_end(&self, event: &Event) -> bool {
+        self.end_dt
+            .map(|end_dt| event.start_dt >= end_dt)
+            .unwrap_or(false)
+    }
+
+    /// Cannot be BYMONTHDAY, BYYEARDAY.
     ///
     /// BYDAY cannot specify a numeric value
     ///
@@ -48,7 +115,13 @@ impl EventIter {
                     match &rrule.until {
                         Some(until_date) if next_date > *until_date => return None,
                         _ => {
-                            if rrule.byday_matches(&next_date) {
+                            // Without BYDAY, the occurrences fall on the weekday of DTSTART.
+                            let weekday_matches = if rrule.byday.is_empty() {
+                                next_date.weekday() == self.original_event.start_dt.weekday()
+                            } else {
+                                rrule.byday_matches(&next_date)
+                            };
+                            if weekday_matches {
                                 let mut event = self.original_event.clone();
                                 let diff = next_date - self.original_event.start_dt;
                                 event.end_dt = self.original_event.end_dt + diff;
@@ -62,13 +135,40 @@ impl EventIter {
         }
     }
 
+    /// Supports BYMONTH, BYMONTHDAY, and BYDAY, which limit the days of
+    /// the interval.
+    ///
+    /// BYDAY cannot specify a numeric value
     fn next_daily(&mut self) -> Option<Event> {
-        eprintln!(
-            "WARN: unsupported event frequency: DAILY. Event: {:?}",
-            self.original_event.event.summary().unwrap_or_default()
-        );
-        // TODO
-        None
+        match &self.original_event.rrule {
+            None => None,
+            Some(rrule) => {
+                let mut next_date = self.last_start_dt;
+                loop {
+                    next_date += chrono::Duration::days(rrule.interval as i64);
+                    // A rule matching no day at all, e.g., BYMONTH=2;BYMONTHDAY=30,
+                    // must not loop forever: the calendar repeats every 400 years.
+                    if next_date.year() - self.last_start_dt.year() > 400 * rrule.interval as i32 {
+                        return None;
+                    }
+                    match &rrule.until {
+                        Some(until_date) if next_date > *until_date => return None,
+                        _ => {
+                            if rrule.bymonth_matches(&next_date)
+                                && rrule.bymonthday_matches(&next_date)
+                                && rrule.byday_matches(&next_date)
+                            {
+                                let mut event = self.original_event.clone();
+                                let diff = next_date - self.original_event.start_dt;
+                                event.end_dt = self.original_event.end_dt + diff;
+                                event.start_dt = next_date;
+                                return Some(event);
+                            }
+                        }
+                    }
+                }
+            }
+        }
     }
 
     fn next_monthly(&mut self) -> Option<Event> {
@@ -81,15 +181,15 @@ impl EventIter {
                 // byday
                 // (unsupported) bysetpos
                 if !rrule.bymonth.is_empty() {
-                    eprintln!(
-                        "WARN: unsupported MONTHLY event RRULE: BYMONTH is not supported. Event: {:?}",
+                    log::warn!(
+                        "unsupported MONTHLY event RRULE: BYMONTH is not supported. Event: {:?}",
                         self.original_event.event.summary().unwrap_or_default()
                     );
                     return None;
                 }
                 if !rrule.bysetpos.is_empty() {
-                    eprintln!(
-                        "WARN: unsupported MONTHLY event RRULE: BYSETPOS not supported. Event: {:?}",
+                    log::warn!(
+                        "unsupported MONTHLY event RRULE: BYSETPOS not supported. Event: {:?}",
                         self.original_event.event.summary().unwrap_or_default()
                     );
                     return None;
@@ -105,9 +205,16 @@ impl EventIter {
                     match &rrule.until {
                         Some(until_date) if next_date > *until_date => return None,
                         _ => {
-                            if rrule.bymonthday_matches(&next_date)
-                                && rrule.byday_matches(&next_date)
-                            {
+                            // Without BYMONTHDAY and BYDAY, the occurrences fall
+                            // on the day of the month of DTSTART.
+                            let day_matches =
+                                if rrule.bymonthday.is_empty() && rrule.byday.is_empty() {
+                                    next_date.day() == self.original_event.start_dt.day()
+                                } else {
+                                    rrule.bymonthday_matches(&next_date)
+                                        && rrule.byday_matches(&next_date)
+                                };
+                            if day_matches {
                                 let mut event = self.original_event.clone();
                                 let diff = next_date - self.original_event.start_dt;
                                 event.end_dt = self.original_event.end_dt + diff;
@@ -121,13 +228,73 @@ impl EventIter {
         }
     }
 
+    /// Supports BYMONTH, BYWEEKNO, BYYEARDAY, BYMONTHDAY, and BYDAY.
+    ///
+    /// Without BYWEEKNO, BYYEARDAY, BYMONTHDAY, and BYDAY, the occurrences
+    /// fall on the month and day of DTSTART. With BYWEEKNO but without BYDAY,
+    /// they fall on the weekday of DTSTART.
+    ///
+    /// TODO: BYDAY with a numeric value counts within the month, not the year.
     fn next_yearly(&mut self) -> Option<Event> {
-        // TODO
-        eprintln!(
-            "WARN: unsupported event frequency: YEARLY. Event: {:?}",
-            self.original_event.event.summary().unwrap_or_default()
-        );
-        None
+        match &self.original_event.rrule {
+            None => None,
+            Some(rrule) => {
+                if !rrule.bysetpos.is_empty() {
+                    log::warn!(
+                        "unsupported YEARLY event RRULE: BYSETPOS is not supported. Event: {:?}",
+                        self.original_event.event.summary().unwrap_or_default()
+                    );
+                    return None;
+                }
+                let start_dt = self.original_event.start_dt;
+                let by_date = rrule.byweekno.is_empty()
+                    && rrule.byyearday.is_empty()
+                    && rrule.bymonthday.is_empty()
+                    && rrule.byday.is_empty();
+                let matches = |dt: &DateTime<Utc>| {
+                    let default_day = if by_date {
+                        dt.day() == start_dt.day()
+                            && (!rrule.bymonth.is_empty() || dt.month() == start_dt.month())
+                    } else if !rrule.byweekno.is_empty() && rrule.byday.is_empty() {
+                        dt.weekday() == start_dt.weekday()
+                    } else {
+                        true
+                    };
+                    default_day
+                        && rrule.bymonth_matches(dt)
+                        && rrule.byweekno_matches(dt)
+                        && rrule.byyearday_matches(dt)
+                        && rrule.bymonthday_matches(dt)
+                        && rrule.byday_matches(dt)
+                };
+                let mut next_date = self.last_start_dt;
+                loop {
+                    next_date += chrono::Duration::days(1);
+                    if next_date.ordinal() == 1 {
+                        next_date = next_date
+                            .checked_add_months(Months::new(12 * (rrule.interval - 1)))
+                            .unwrap();
+                    }
+                    // A rule matching no day at all, e.g., BYMONTH=2;BYMONTHDAY=30,
+                    // must not loop forever: the calendar repeats every 400 years.
+                    if next_date.year() - self.last_start_dt.year() > 400 * rrule.interval as i32 {
+                        return None;
+                    }
+                    match &rrule.until {
+                        Some(until_date) if next_date > *until_date => return None,
+                        _ => {
+                            if matches(&next_date) {
+                                let mut event = self.original_event.clone();
+                                let diff = next_date - self.original_event.start_dt;
+                                event.end_dt = self.original_event.end_dt + diff;
+                                event.start_dt = next_date;
+                                return Some(event);
+                            }
+                        }
+                    }
+                }
+            }
+        }
     }
 }
 
@@ -135,7 +302,15 @@ impl Iterator for EventIter {
     type Item = Event;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.truncated {
+            return None;
+        }
         match self.count {
+            0 if self.is_past_end(&self.original_event) => None,
+            0 if self.max_occurrences == 0 => {
+                self.truncated = true;
+                None
+            }
             0 => {
                 self.count += 1;
                 Some(self.original_event.clone())
@@ -155,8 +330,8 @@ impl Iterator for EventIter {
                         &EventFrequency::Monthly => self.next_monthly(),
                         &EventFrequency::Yearly => self.next_yearly(),
                         freq => {
-                            eprintln!(
-                                "WARN: unsupported event frequency: {:?}. Event: {:?}",
+                            log::warn!(
+                                "unsupported event frequency: {:?}. Event: {:?}",
                                 freq,
                                 self.original_event.event.summary().unwrap_or_default()
                             );
@@ -164,6 +339,31 @@ impl Iterator for EventIter {
                         }
                     };
                     if let Some(next) = next {
+                        if self.is_past_end(&next) {
+                            return None;
+                        }
+                        // Only an occurrence that COUNT, UNTIL, and `end_dt`
+                        // all let through is truncated.
+                        if self.count >= self.max_occurrences {
+                            log::warn!(
+                                "recurrence expansion stopped after {} occurrences. Event: {:?}",
+                                self.max_occurrences,
+                                self.original_event.event.summary().unwrap_or_default()
+                            );
+                            self.truncated = true;
+                            return None;
+                        }
+                        // The steppers start from the day after the last
+                        // occurrence, so an occurrence never repeats the
+                        // previous one (DTSTART included). Guard the invariant.
+                        if next.start_dt <= self.last_start_dt {
+                            log::warn!(
+                                "recurrence did not advance past {}. Event: {:?}",
+                                self.last_start_dt,
+                                self.original_event.event.summary().unwrap_or_default()
+                            );
+                            return None;
+                        }
                         self.count += 1;
                         self.last_start_dt = next.start_dt;
                         Some(next)
@@ -185,8 +385,7 @@ impl Summary for IcalEvent {
         self.properties
             .iter()
             .find(|p| p.name.to_uppercase() == "SUMMARY")
-            .map(|p| p.value.clone())
-            .flatten()
+            .and_then(|p| p.value.clone())
     }
 }
 
@@ -199,30 +398,86 @@ impl StartDate for IcalEvent {
         self.properties
             .iter()
             .find(|p| p.name.to_uppercase() == "DTSTART")
-            .map(|p| Event::parse_dtstart(p).ok())
-            .flatten()
+            .and_then(|p| Event::parse_dtstart(p, &TimeZones::default()).ok())
+            .map(|(start_dt, _)| start_dt)
     }
 }
 
 #[derive(Clone)]
 pub struct Event {
-    pub(crate) uid: String,
-    pub(crate) start_dt: DateTime<Utc>,
-    pub(crate) end_dt: DateTime<Utc>,
-    pub(crate) rrule: Option<RRule>,
-    pub(crate) event: IcalEvent,
+    /// The UID, or a synthetic one derived from the start, the end, and the
+    /// summary when the event has none.
+    pub uid: String,
+    pub start_dt: DateTime<Utc>,
+    /// Where the timezone of DTSTART comes from.
+    pub start_zone: ZoneSource,
+    pub end_dt: DateTime<Utc>,
+    pub rrule: Option<RRule>,
+    pub event: IcalEvent,
+    /// Both DTSTART and DTEND (if present) are dates without a time.
+    pub all_day: bool,
+    /// Neither DTEND nor DURATION is set; `end_dt` equals `start_dt`.
+    pub open_ended: bool,
 
-    #[allow(unused)]
-    pub(crate) created_dt: DateTime<Utc>,
+    /// CREATED is optional (RFC 5545 Section 3.8.7.1).
+    pub created_dt: Option<DateTime<Utc>>,
 }
 
 impl Event {
-    pub(crate) fn recurring(&self) -> EventIter {
+    pub fn recurring(&self) -> EventIter {
         EventIter::from(self.clone())
     }
 
+    /// The occurrences of the event starting at or after `start_dt` and
+    /// before `end_dt`.
+    ///
+    /// ```
+    /// use calvest::Event;
+    /// use chrono::{TimeZone, Utc};
+    /// use ical::IcalParser;
+    ///
+    /// let ics = "BEGIN:VCALENDAR\r
+    /// BEGIN:VEVENT\r
+    /// UID:standup\r
+    /// SUMMARY:Standup\r
+    /// CREATED:20250101T000000Z\r
+    /// DTSTART:20250106T090000Z\r
+    /// DTEND:20250106T091500Z\r
+    /// RRULE:FREQ=WEEKLY;BYDAY=MO\r
+    /// END:VEVENT\r
+    /// END:VCALENDAR\r
+    /// ";
+    /// let calendar = IcalParser::new(ics.as_bytes()).next().unwrap().unwrap();
+    /// let event = Event::try_from(calendar.events[0].clone()).unwrap();
+    /// let occurrences = event.occurrences_between(
+    ///     Utc.with_ymd_and_hms(2025, 1, 10, 0, 0, 0).unwrap(),
+    ///     Utc.with_ymd_and_hms(2025, 2, 1, 0, 0, 0).unwrap(),
+    /// );
+    /// let starts = occurrences
+    ///     .iter()
+    ///     .map(|event| event.start_dt.format("%Y-%m-%d").to_string())
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(starts, ["2025-01-13", "2025-01-20", "2025-01-27"]);
+    /// ```
+    pub fn occurrences_between(
+        &self,
+        start_dt: DateTime<Utc>,
+        end_dt: DateTime<Utc>,
+    ) -> Vec<Event> {
+        self.recurring()
+            .until(end_dt)
+            .skip_while(|event| event.start_dt < start_dt)
+            .collect()
+    }
+
+    /// The key identifying the event: its UID, shared by all the occurrences
+    /// of a recurring event, so a duplicate also has the same start.
+    pub fn dedup_key(&self) -> String {
+        self.uid.clone()
+    }
+
     #[allow(unused)]
-    pub(crate) fn starts_within(
+    pub fn starts_within(
         &self,
         start_date: &Option<DateTime<Utc>>,
         end_date: &Option<DateTime<Utc>>,
@@ -235,6 +490,20 @@ impl Event {
         }
     }
 
+    /// A UID for an event without one, stable across runs: the FNV-1a hash
+    /// of the start, the end, and the summary.
+    fn synthetic_uid(start_dt: DateTime<Utc>, end_dt: DateTime<Utc>, summary: &str) -> String {
+        let key = format!(
+            "{}\n{}\n{summary}",
+            start_dt.to_rfc3339(),
+            end_dt.to_rfc3339()
+        );
+        let hash = key.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        format!("{hash:016x}@calvest")
+    }
+
     fn parse_uuid(prop: &IcalProperty) -> Result<String> {
         Ok(prop
             .value
@@ -243,41 +512,58 @@ impl Event {
             .clone())
     }
 
-    fn parse_created(prop: &IcalProperty) -> Result<DateTime<Utc>> {
+    fn parse_created(prop: &IcalProperty, timezones: &TimeZones) -> Result<DateTime<Utc>> {
         let value = prop
             .value
             .as_ref()
             .ok_or(anyhow!("No value (datetime) for `CREATED` property"))?;
-        let date = parse::datetime(value, &prop.params)
+        let date = parse::datetime(value, &prop.params, timezones)
             .map_err(|e| anyhow!("Invalid ical date {prop:?}\n{e}"))?;
         Ok(date)
     }
 
-    fn parse_dtend(prop: &IcalProperty) -> Result<DateTime<Utc>> {
+    fn parse_dtend(
+        prop: &IcalProperty,
+        timezones: &TimeZones,
+    ) -> Result<(DateTime<Utc>, Option<DstAdjustment>)> {
         let value = prop
             .value
             .as_ref()
             .ok_or(anyhow!("No value (datetime) for `DTEND` property"))?;
-        Ok(parse::datetime(value, &prop.params)
-            .map_err(|e| anyhow!("Invalid ical date {prop:?}\n{e}"))?)
+        parse::localized_datetime(value, &prop.params, timezones)
+            .map_err(|e| anyhow!("Invalid ical date {prop:?}\n{e}"))
+    }
+
+    fn parse_duration(prop: &IcalProperty) -> Result<chrono::Duration> {
+        let value = prop
+            .value
+            .as_ref()
+            .ok_or(anyhow!("No value (duration) for `DURATION` property"))?;
+        parse::duration(value).map_err(|e| anyhow!("Invalid ical duration {prop:?}\n{e}"))
     }
 
-    fn parse_dtstart(prop: &IcalProperty) -> Result<DateTime<Utc>> {
+    fn parse_dtstart(
+        prop: &IcalProperty,
+        timezones: &TimeZones,
+    ) -> Result<(DateTime<Utc>, Option<DstAdjustment>)> {
         let value = prop
             .value
             .as_ref()
             .ok_or(anyhow!("No value (datetime) for `DTSTART` property"))?;
-        let date = parse::datetime(value, &prop.params)
-            .map_err(|e| anyhow!("Invalid ical date {prop:?}\n{e}"))?;
-        Ok(date)
+        parse::localized_datetime(value, &prop.params, timezones)
+            .map_err(|e| anyhow!("Invalid ical date {prop:?}\n{e}"))
     }
 
-    fn parse_rrule(prop: &IcalProperty) -> Result<RRule> {
+    fn parse_rrule(
+        prop: &IcalProperty,
+        dtstart_params: &Option<Vec<(String, Vec<String>)>>,
+        timezones: &TimeZones,
+    ) -> Result<RRule> {
         let rrule = prop
             .value
             .as_ref()
             .ok_or(anyhow!("invalid RRULE: {}", prop.to_string()))?;
-        Ok(RRule::from_str(rrule)?)
+        RRule::from_str_in_zone(rrule, dtstart_params, timezones)
     }
 }
 
@@ -285,44 +571,400 @@ impl TryFrom<IcalEvent> for Event {
     type Error = anyhow::Error;
 
     fn try_from(event: IcalEvent) -> Result<Self> {
+        Self::new(event, &TimeZones::default())
+    }
+}
+
+impl Event {
+    /// Creates the event resolving its TZIDs against the calendar `timezones`.
+    pub fn new(event: IcalEvent, timezones: &TimeZones) -> Result<Self> {
         let mut start_dt = None;
+        let mut start_zone = None;
         let mut end_dt = None;
+        let mut duration = None;
+        let mut start_is_date = false;
+        let mut end_is_date = true;
         let mut created_dt = None;
         let mut uid = None;
         let mut rrule = None;
+        let mut dtstart_params = None;
+        let mut rrule_prop = None;
+        // Reported once the UID and the SUMMARY are known.
+        let mut adjustments = vec![];
         for prop in event.properties.iter() {
             match prop.name.as_str() {
-                "DTSTART" => start_dt = Some(Self::parse_dtstart(prop)?),
-                "DTEND" => end_dt = Some(Self::parse_dtend(prop)?),
-                "CREATED" => created_dt = Some(Self::parse_created(prop)?),
+                "DTSTART" => {
+                    let (dt, adjustment) = Self::parse_dtstart(prop, timezones)?;
+                    start_dt = Some(dt);
+                    adjustments.extend(adjustment.map(|a| (prop, a)));
+                    start_zone = prop
+                        .value
+                        .as_ref()
+                        .map(|value| parse::zone_source(value, &prop.params, timezones));
+                    start_is_date = parse::is_date(&prop.params);
+                    dtstart_params = prop.params.clone();
+                }
+                "DTEND" => {
+                    let (dt, adjustment) = Self::parse_dtend(prop, timezones)?;
+                    end_dt = Some(dt);
+                    adjustments.extend(adjustment.map(|a| (prop, a)));
+                    end_is_date = parse::is_date(&prop.params);
+                }
+                "DURATION" => duration = Some(Self::parse_duration(prop)?),
+                "CREATED" => created_dt = Some(Self::parse_created(prop, timezones)?),
                 "UID" => uid = Some(Self::parse_uuid(prop)?),
-                "RRULE" => rrule = Some(Self::parse_rrule(prop)?),
+                "RRULE" => rrule_prop = Some(prop),
                 _ => {}
             }
         }
-        Ok(Self {
-            start_dt: start_dt.ok_or(anyhow!(
-                "Unsupported event: no DTSTART. Event: UID={:?} CREATED={:?}",
-                uid,
-                created_dt
-            ))?,
-            end_dt: end_dt.ok_or(anyhow!(
-                "Unsupported event: no DTEND. Event: UID={:?} CREATED={:?}",
+        // The RRULE may precede DTSTART, whose timezone its UNTIL needs.
+        if let Some(prop) = rrule_prop {
+            rrule = Some(Self::parse_rrule(prop, &dtstart_params, timezones)?);
+        }
+        if end_dt.is_none() {
+            end_dt = start_dt
+                .zip(duration)
+                .map(|(start_dt, duration)| start_dt + duration);
+        }
+        let (Some(start_dt), Some(start_zone)) = (start_dt, start_zone) else {
+            // Report every missing property at once.
+            let missing = if end_dt.is_none() && duration.is_none() {
+                "no DTSTART, no DTEND or DURATION"
+            } else {
+                "no DTSTART"
+            };
+            return Err(anyhow!(
+                "Unsupported event: {missing}. Event: UID={:?} SUMMARY={:?}",
                 uid,
-                created_dt
-            ))?,
-            created_dt: created_dt.ok_or(anyhow!(
-                "Unsupported event: no CREATED. Event: UID={:?} DTSTART={:?}",
+                event.summary()
+            ));
+        };
+        // Without DTEND and DURATION, a date event lasts one day and
+        // a date-time event ends when it starts (RFC 5545 Section 3.6.1).
+        let open_ended = end_dt.is_none() && !start_is_date;
+        let end_dt = end_dt.unwrap_or(if start_is_date {
+            start_dt + chrono::Duration::days(1)
+        } else {
+            start_dt
+        });
+        let uid = uid.unwrap_or_else(|| {
+            Self::synthetic_uid(start_dt, end_dt, &event.summary().unwrap_or_default())
+        });
+        for (prop, adjustment) in adjustments {
+            log::warn!(
+                "{} {:?}: {adjustment}. Event: UID={:?} SUMMARY={:?}",
+                prop.name,
+                prop.value.as_deref().unwrap_or_default(),
                 uid,
-                start_dt
-            ))?,
-            uid: uid.ok_or(anyhow!(
-                "Unsupported event: no UID. Event: DTSTART={:?} CREATED={:?}",
-                start_dt,
-                created_dt
-            ))?,
+                event.summary()
+            );
+        }
+        Ok(Self {
+            start_dt,
+            start_zone,
+            end_dt,
+            open_ended,
+            created_dt,
+            uid,
             event,
+            all_day: start_is_date && end_is_date,
             rrule,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use ical::IcalParser;
+
+    fn parse_event(properties: &str) -> Event {
+        let ics = format!(
+            "BEGIN:VCALENDAR\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:standup\r\n\
+             SUMMARY:Standup\r\n\
+             {properties}\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n"
+        );
+        let calendar = IcalParser::new(ics.as_bytes()).next().unwrap().unwrap();
+        Event::try_from(calendar.events[0].clone()).unwrap()
+    }
+
+    fn event(rrule: &str) -> Event {
+        parse_event(&format!(
+            "DTSTART:20250106T090000Z\r\n\
+             DTEND:20250106T091500Z\r\n\
+             RRULE:{rrule}\r\n"
+        ))
+    }
+
+    fn starts(events: impl Iterator<Item = Event>) -> Vec<String> {
+        events
+            .map(|event| event.start_dt.format("%Y-%m-%d").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn daily_count_terminates() {
+        let event = event("FREQ=DAILY;INTERVAL=2;COUNT=3");
+        assert_eq!(
+            starts(event.recurring()),
+            ["2025-01-06", "2025-01-08", "2025-01-10"]
+        );
+    }
+
+    #[test]
+    fn daily_until_terminates() {
+        let event = event("FREQ=DAILY;BYDAY=MO,TU,WE,TH,FR;UNTIL=20250113T090000Z");
+        assert_eq!(
+            starts(event.recurring()),
+            [
+                "2025-01-06",
+                "2025-01-07",
+                "2025-01-08",
+                "2025-01-09",
+                "2025-01-10",
+                "2025-01-13"
+            ]
+        );
+    }
+
+    #[test]
+    fn unbounded_daily_clipped_by_end_date_terminates() {
+        let event = event("FREQ=DAILY");
+        let end_dt = Utc.with_ymd_and_hms(2025, 1, 9, 0, 0, 0).unwrap();
+        assert_eq!(
+            starts(event.recurring().until(end_dt)),
+            ["2025-01-06", "2025-01-07", "2025-01-08"]
+        );
+    }
+
+    #[test]
+    fn unbounded_daily_stops_at_max_occurrences() {
+        let event = event("FREQ=DAILY");
+        let mut occurrences = event.recurring().max_occurrences(2);
+        assert_eq!(occurrences.by_ref().count(), 2);
+        assert!(occurrences.truncated());
+        assert_eq!(event.recurring().count(), DEFAULT_MAX_OCCURRENCES as usize);
+    }
+
+    #[test]
+    fn count_at_max_occurrences_is_not_truncated() {
+        let end_dt = Utc.with_ymd_and_hms(2025, 1, 9, 0, 0, 0).unwrap();
+        for mut occurrences in [
+            event("FREQ=DAILY;COUNT=3").recurring().max_occurrences(3),
+            event("FREQ=DAILY;UNTIL=20250108T090000Z")
+                .recurring()
+                .max_occurrences(3),
+            event("FREQ=DAILY")
+                .recurring()
+                .max_occurrences(3)
+                .until(end_dt),
+        ] {
+            assert_eq!(occurrences.by_ref().count(), 3);
+            assert!(!occurrences.truncated());
+        }
+    }
+
+    #[test]
+    fn daily_matching_no_day_terminates() {
+        let event = event("FREQ=DAILY;BYMONTH=2;BYMONTHDAY=30");
+        assert_eq!(starts(event.recurring()), ["2025-01-06"]);
+    }
+
+    #[test]
+    fn duration_sets_the_end() {
+        let event = parse_event("DTSTART:20250106T090000Z\r\nDURATION:PT1H30M\r\n");
+        assert_eq!(event.end_dt - event.start_dt, chrono::Duration::minutes(90));
+        assert!(!event.open_ended);
+
+        let event = parse_event("DTSTART;VALUE=DATE:20250106\r\nDURATION:P1D\r\n");
+        assert_eq!(event.end_dt - event.start_dt, chrono::Duration::days(1));
+        assert!(event.all_day);
+    }
+
+    #[test]
+    fn yearly_byweekno_across_the_year_boundary() {
+        let event = parse_event(
+            "DTSTART:20241223T090000Z\r\n\
+             DTEND:20241223T100000Z\r\n\
+             RRULE:FREQ=YEARLY;BYWEEKNO=1,52;COUNT=4\r\n",
+        );
+        // 2024-12-30 is in week 1 of 2025, 2025-12-29 in week 1 of 2026.
+        assert_eq!(
+            starts(event.recurring()),
+            ["2024-12-23", "2024-12-30", "2025-12-22", "2025-12-29"]
+        );
+    }
+
+    #[test]
+    fn weekly_and_monthly_fall_on_the_day_of_dtstart() {
+        let weekly = event("FREQ=WEEKLY;INTERVAL=2;COUNT=3");
+        assert_eq!(
+            starts(weekly.recurring()),
+            ["2025-01-06", "2025-01-20", "2025-02-03"]
+        );
+        let monthly = event("FREQ=MONTHLY;COUNT=3");
+        assert_eq!(
+            starts(monthly.recurring()),
+            ["2025-01-06", "2025-02-06", "2025-03-06"]
+        );
+    }
+
+    #[test]
+    fn weekly_byday_on_the_dtstart_weekday_does_not_repeat_it() {
+        // 2025-01-06 is a Monday.
+        let weekly = event("FREQ=WEEKLY;BYDAY=MO;COUNT=3");
+        assert_eq!(
+            starts(weekly.recurring()),
+            ["2025-01-06", "2025-01-13", "2025-01-20"]
+        );
+        let weekly = event("FREQ=WEEKLY;BYDAY=MO,WE;COUNT=4");
+        assert_eq!(
+            starts(weekly.recurring()),
+            ["2025-01-06", "2025-01-08", "2025-01-13", "2025-01-15"]
+        );
+    }
+
+    #[test]
+    fn dtstart_outside_byday_is_the_first_occurrence() {
+        // 2025-01-06 is a Monday; COUNT includes it.
+        let weekly = event("FREQ=WEEKLY;BYDAY=TU,TH;COUNT=3");
+        assert_eq!(
+            starts(weekly.recurring()),
+            ["2025-01-06", "2025-01-07", "2025-01-09"]
+        );
+    }
+
+    #[test]
+    fn local_until_is_in_the_timezone_of_dtstart() {
+        let daily = |until: &str| {
+            parse_event(&format!(
+                "DTSTART;TZID=America/New_York:20250106T090000\r\n\
+                 DTEND;TZID=America/New_York:20250106T093000\r\n\
+                 RRULE:FREQ=DAILY;UNTIL={until}\r\n"
+            ))
+        };
+        // 09:00 in New York is 14:00 UTC, after an UNTIL of 09:00 UTC.
+        assert_eq!(
+            starts(daily("20250108T090000").recurring()),
+            ["2025-01-06", "2025-01-07", "2025-01-08"]
+        );
+        assert_eq!(
+            starts(daily("20250108T085959").recurring()),
+            ["2025-01-06", "2025-01-07"]
+        );
+        assert_eq!(
+            starts(daily("20250108T140000Z").recurring()),
+            ["2025-01-06", "2025-01-07", "2025-01-08"]
+        );
+    }
+
+    #[test]
+    fn occurrences_between_a_partially_overlapping_window() {
+        let weekly = event("FREQ=WEEKLY;COUNT=6");
+        let dt = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().to_utc();
+        let between = |start: &str, end: &str| {
+            starts(weekly.occurrences_between(dt(start), dt(end)).into_iter())
+        };
+        // The series runs from 2025-01-06 to 2025-02-10.
+        assert_eq!(
+            between("2025-01-10T00:00:00Z", "2025-01-28T00:00:00Z"),
+            ["2025-01-13", "2025-01-20", "2025-01-27"]
+        );
+        assert_eq!(
+            between("2025-02-01T00:00:00Z", "2025-03-01T00:00:00Z"),
+            ["2025-02-03", "2025-02-10"]
+        );
+        // The start is inclusive and the end exclusive.
+        assert_eq!(
+            between("2025-01-13T09:00:00Z", "2025-01-27T09:00:00Z"),
+            ["2025-01-13", "2025-01-20"]
+        );
+        assert!(between("2024-12-01T00:00:00Z", "2025-01-06T00:00:00Z").is_empty());
+    }
+
+    #[test]
+    fn created_is_optional() {
+        let without = parse_event(
+            "DTSTART:20250106T090000Z\r\n\
+             DTEND:20250106T091500Z\r\n",
+        );
+        assert_eq!(without.created_dt, None);
+        assert_eq!(
+            without.start_dt,
+            Utc.with_ymd_and_hms(2025, 1, 6, 9, 0, 0).unwrap()
+        );
+        let with = parse_event(
+            "CREATED:20250101T000000Z\r\n\
+             DTSTART:20250106T090000Z\r\n\
+             DTEND:20250106T091500Z\r\n",
+        );
+        assert_eq!(
+            with.created_dt,
+            Some(Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn monthly_fifth_weekday_skips_months_without_one() {
+        let event = parse_event(
+            "DTSTART:20250331T090000Z\r\n\
+             DTEND:20250331T100000Z\r\n\
+             RRULE:FREQ=MONTHLY;BYDAY=5MO;COUNT=3\r\n",
+        );
+        assert_eq!(
+            starts(event.recurring()),
+            ["2025-03-31", "2025-06-30", "2025-09-29"]
+        );
+    }
+
+    #[test]
+    fn monthly_fifth_last_weekday_skips_months_without_one() {
+        let event = parse_event(
+            "DTSTART:20250103T090000Z\r\n\
+             DTEND:20250103T100000Z\r\n\
+             RRULE:FREQ=MONTHLY;BYDAY=-5FR;COUNT=3\r\n",
+        );
+        assert_eq!(
+            starts(event.recurring()),
+            ["2025-01-03", "2025-05-02", "2025-08-01"]
+        );
+    }
+
+    #[test]
+    fn yearly_on_the_day_of_dtstart() {
+        let event = event("FREQ=YEARLY;INTERVAL=2;COUNT=3");
+        assert_eq!(
+            starts(event.recurring()),
+            ["2025-01-06", "2027-01-06", "2029-01-06"]
+        );
+    }
+
+    #[test]
+    fn yearly_byyearday_first_and_last_day() {
+        let event = parse_event(
+            "DTSTART:20240101T090000Z\r\n\
+             DTEND:20240101T100000Z\r\n\
+             RRULE:FREQ=YEARLY;BYYEARDAY=1,-1;COUNT=4\r\n",
+        );
+        assert_eq!(
+            starts(event.recurring()),
+            ["2024-01-01", "2024-12-31", "2025-01-01", "2025-12-31"]
+        );
+    }
+
+    #[test]
+    fn synthetic_uid_is_stable_and_distinct() {
+        let start = Utc.with_ymd_and_hms(2025, 1, 6, 9, 0, 0).unwrap();
+        let end = start + chrono::Duration::minutes(15);
+        let uid = Event::synthetic_uid(start, end, "Standup");
+        assert!(uid.ends_with("@calvest"), "{uid}");
+        assert_eq!(uid, Event::synthetic_uid(start, end, "Standup"));
+        assert_ne!(uid, Event::synthetic_uid(start, end, "Review"));
+        assert_ne!(uid, Event::synthetic_uid(start, start, "Standup"));
+    }
+}
@@ -1,57 +1,300 @@
+use crate::ical::tz;
+use crate::ical::tz::TimeZones;
 use anyhow::anyhow;
 use anyhow::Result;
 use chrono::DateTime;
+use chrono::Days;
+use chrono::Duration;
+use chrono::FixedOffset;
 use chrono::Local;
+use chrono::LocalResult;
+use chrono::NaiveDate;
 use chrono::NaiveDateTime;
+use chrono::NaiveTime;
+use chrono::TimeZone;
 use chrono::Utc;
 use chrono::Weekday;
 use chrono_tz::Tz;
 use core::str;
 
-pub(crate) fn datetime(
-    s: &str,
-    params: &Option<Vec<(String, Vec<String>)>>,
-) -> Result<DateTime<Utc>> {
-    let is_date = params
+/// Whether the property params declare a date-only value (`VALUE=DATE`).
+pub fn is_date(params: &Option<Vec<(String, Vec<String>)>>) -> bool {
+    params
         .as_ref()
         .map(Vec::as_slice)
         .unwrap_or_default()
         .iter()
-        .find(|(n, v)| {
+        .any(|(n, v)| {
             n.to_uppercase().as_str() == "VALUE"
                 && v.first()
                     .map(|v| v.to_uppercase().as_str() == "DATE")
                     .unwrap_or(false)
         })
-        .is_some();
-    let datetime_s = if is_date {
-        s.to_string() + "T000000"
-    } else {
-        s.split_at(15).0.to_string()
+}
+
+/// Accepted shapes of a DATE-TIME value, without the trailing `Z`.
+const DATETIME_FORMATS: &[&str] = &["%Y%m%dT%H%M%S", "%Y%m%dT%H%M"];
+
+/// Parses a DATE or a DATE-TIME value. The end-of-day time `T240000` some
+/// producers emit is the midnight of the next day.
+///
+/// ```
+/// use calvest::ical::parse::naive_datetime;
+///
+/// let end = naive_datetime("20250110T240000", false).unwrap();
+/// assert_eq!(end, naive_datetime("20250111T000000", false).unwrap());
+/// ```
+pub fn naive_datetime(s: &str, is_date: bool) -> Result<NaiveDateTime> {
+    let date = |s: &str| {
+        NaiveDate::parse_from_str(s, "%Y%m%d")
+            .map_err(|e| anyhow!("Invalid date '{}', expected YYYYMMDD: {}", s, e))
     };
+    if is_date {
+        return Ok(date(s)?.and_time(NaiveTime::MIN));
+    }
+    let value = s.strip_suffix('Z').unwrap_or(s);
+    if let Some(day) = value
+        .strip_suffix("T240000")
+        .or_else(|| value.strip_suffix("T2400"))
+    {
+        return Ok((date(day)? + Days::new(1)).and_time(NaiveTime::MIN));
+    }
+    DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .ok_or(anyhow!(
+            "Invalid datetime '{}', expected YYYYMMDDThhmmss[Z]",
+            s
+        ))
+}
+
+/// Where the timezone of a datetime value comes from.
+#[derive(Debug, Clone)]
+pub enum ZoneSource {
+    /// The value ends with `Z`.
+    Utc,
+    /// The TZID refers to a VTIMEZONE of the calendar.
+    VTimeZone(String),
+    /// The TZID is an IANA or a Windows timezone name.
+    Tzid(String),
+    /// There is no TZID; the value is a floating local time, in the given
+    /// timezone or else in the system one.
+    Local(Option<Tz>),
+}
+
+impl std::fmt::Display for ZoneSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Utc => write!(f, "UTC"),
+            Self::VTimeZone(tzid) => write!(f, "TZID={tzid} (VTIMEZONE)"),
+            Self::Tzid(tzid) => write!(f, "TZID={tzid}"),
+            Self::Local(Some(tz)) => write!(f, "Local ({tz})"),
+            Self::Local(None) => write!(f, "Local"),
+        }
+    }
+}
+
+pub fn zone_source(
+    s: &str,
+    params: &Option<Vec<(String, Vec<String>)>>,
+    timezones: &TimeZones,
+) -> ZoneSource {
+    // The trailing `Z` designates UTC regardless of any TZID.
+    if s.ends_with('Z') {
+        return ZoneSource::Utc;
+    }
     let tzid = params
         .as_ref()
         .map(Vec::as_slice)
         .unwrap_or_default()
         .iter()
         .find(|(n, _)| n.to_uppercase().as_str() == "TZID")
-        .map(|p| p.1.first())
-        .flatten();
-    let tz = tzid.map(|tzid| tzid.parse::<Tz>());
-    let datetime = match tz {
-        Some(tz) => NaiveDateTime::parse_from_str(&datetime_s, "%Y%m%dT%H%M%S")?
-            .and_local_timezone(tz?)
-            .unwrap()
-            .to_utc(),
-        None => NaiveDateTime::parse_from_str(&datetime_s, "%Y%m%dT%H%M%S")?
-            .and_local_timezone(Local)
-            .unwrap()
-            .to_utc(),
+        .and_then(|p| p.1.first());
+    match tzid {
+        Some(tzid) if timezones.get(tzid).is_some() => ZoneSource::VTimeZone(tzid.clone()),
+        Some(tzid) => ZoneSource::Tzid(tzid.clone()),
+        None => ZoneSource::Local(timezones.local()),
+    }
+}
+
+/// How [`localized_datetime`] resolved a local time that a DST transition
+/// skips or repeats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DstAdjustment {
+    /// The local time does not exist; it is shifted forward to this instant.
+    Nonexistent(DateTime<Utc>),
+    /// The local time occurs twice; it resolves to the earlier instant.
+    Ambiguous(DateTime<Utc>),
+}
+
+impl std::fmt::Display for DstAdjustment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Nonexistent(dt) => write!(f, "nonexistent local time shifted to {dt}"),
+            Self::Ambiguous(dt) => write!(f, "ambiguous local time resolved to {dt}"),
+        }
+    }
+}
+
+pub fn datetime(
+    s: &str,
+    params: &Option<Vec<(String, Vec<String>)>>,
+    timezones: &TimeZones,
+) -> Result<DateTime<Utc>> {
+    localized_datetime(s, params, timezones).map(|(datetime, _)| datetime)
+}
+
+/// Like [`datetime`], but also tells how a local time in a DST transition
+/// was resolved, so that the caller can report it with the event.
+pub fn localized_datetime(
+    s: &str,
+    params: &Option<Vec<(String, Vec<String>)>>,
+    timezones: &TimeZones,
+) -> Result<(DateTime<Utc>, Option<DstAdjustment>)> {
+    let naive = naive_datetime(s, is_date(params))?;
+    let localized = match zone_source(s, params, timezones) {
+        ZoneSource::Utc => (naive.and_utc(), None),
+        ZoneSource::VTimeZone(tzid) => {
+            let vtimezone = timezones.get(&tzid).unwrap();
+            localize(naive, vtimezone.offset(&naive))
+        }
+        ZoneSource::Tzid(tzid) => localize(naive, tz::timezone(&tzid)?),
+        ZoneSource::Local(Some(tz)) => localize(naive, tz),
+        ZoneSource::Local(None) => localize(naive, Local),
     };
-    Ok(datetime)
+    Ok(localized)
 }
 
-pub(crate) fn week_day(s: &str) -> Result<Weekday> {
+/// Resolves the local time in the timezone.
+///
+/// A local time skipped by a DST transition is shifted forward to the first
+/// valid minute after the gap. An ambiguous local time resolves to the
+/// earlier offset.
+fn localize<T: TimeZone>(naive: NaiveDateTime, tz: T) -> (DateTime<Utc>, Option<DstAdjustment>) {
+    let mut local = naive;
+    loop {
+        match local.and_local_timezone(tz.clone()) {
+            LocalResult::Single(dt) => {
+                let dt = dt.to_utc();
+                let adjustment = (local != naive).then_some(DstAdjustment::Nonexistent(dt));
+                return (dt, adjustment);
+            }
+            LocalResult::Ambiguous(earliest, _) => {
+                let dt = earliest.to_utc();
+                return (dt, Some(DstAdjustment::Ambiguous(dt)));
+            }
+            LocalResult::None => local += Duration::minutes(1),
+        }
+    }
+}
+
+/// Parses a UTC offset value, e.g., `+0100`, `-0530`, or `+013045`.
+pub fn utc_offset(s: &str) -> Result<FixedOffset> {
+    let invalid = || anyhow!("Invalid UTC offset '{}'", s);
+    let sign = match s.as_bytes().first() {
+        Some(b'+') => 1,
+        Some(b'-') => -1,
+        _ => return Err(invalid()),
+    };
+    let digits = &s[1..];
+    if !matches!(digits.len(), 4 | 6) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let hours = digits[0..2].parse::<i32>()?;
+    let minutes = digits[2..4].parse::<i32>()?;
+    let seconds = digits.get(4..6).map(str::parse::<i32>).unwrap_or(Ok(0))?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60 + seconds)).ok_or_else(invalid)
+}
+
+/// Parses an RFC 5545 event duration value, e.g., `PT1H30M`, `P1DT2H`, or
+/// `P2W`.
+///
+/// The designators are uppercase and come in the order `W`, `D`, `T`, `H`,
+/// `M`, `S`, each at most once. Weeks cannot be combined with the other
+/// designators, and the duration of an event cannot be negative
+/// (RFC 5545 Section 3.8.2.5).
+pub fn duration(s: &str) -> Result<Duration> {
+    let invalid = || anyhow!("Invalid DURATION '{}', expected e.g. P2W or P1DT2H30M", s);
+    let rest = match s.as_bytes().first() {
+        Some(b'-') => return Err(anyhow!("Invalid DURATION '{}', cannot be negative", s)),
+        Some(b'+') => &s[1..],
+        _ => s,
+    };
+    let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+    const UNITS: &str = "WDHMS";
+    let mut duration = Duration::zero();
+    let mut in_time = false;
+    let mut number = String::new();
+    let mut units = 0;
+    let mut weeks = false;
+    // The position in UNITS the next designator must be after.
+    let mut last_unit = None;
+    for c in rest.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' if !in_time && number.is_empty() && !weeks => {
+                in_time = true;
+                units = 0;
+            }
+            _ => {
+                let n = number.parse::<i64>().map_err(|_| invalid())?;
+                number.clear();
+                let unit = UNITS.find(c).ok_or_else(invalid)?;
+                if weeks || last_unit.is_some_and(|last_unit| unit <= last_unit) {
+                    return Err(invalid());
+                }
+                last_unit = Some(unit);
+                duration += match (in_time, c) {
+                    (false, 'W') => {
+                        weeks = true;
+                        Duration::weeks(n)
+                    }
+                    (false, 'D') => Duration::days(n),
+                    (true, 'H') => Duration::hours(n),
+                    (true, 'M') => Duration::minutes(n),
+                    (true, 'S') => Duration::seconds(n),
+                    _ => return Err(invalid()),
+                };
+                units += 1;
+            }
+        }
+    }
+    // Also rejects a `T` without the time designators after it.
+    if !number.is_empty() || units == 0 {
+        return Err(invalid());
+    }
+    Ok(duration)
+}
+
+/// The email address of a CAL-ADDRESS value, lowercased, without `mailto:`.
+pub fn cal_address(s: &str) -> String {
+    let s = s.trim();
+    match s.get(..7) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => &s[7..],
+        _ => s,
+    }
+    .to_lowercase()
+}
+
+/// Unescapes an RFC 5545 TEXT value: `\\`, `\;`, `\,`, and `\n` (or `\N`).
+pub fn text(s: &str) -> String {
+    let mut text = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => text.push('\n'),
+            Some(c) => text.push(c),
+            None => text.push('\\'),
+        }
+    }
+    text
+}
+
+pub fn week_day(s: &str) -> Result<Weekday> {
     match s {
         "MO" => Ok(Weekday::Mon),
         "TU" => Ok(Weekday::Tue),
@@ -63,3 +306,186 @@ pub(crate) fn week_day(s: &str) -> Result<Weekday> {
         _ => Err(anyhow!("Unsupported BYDAY {}", s)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tzid(tzid: &str) -> Option<Vec<(String, Vec<String>)>> {
+        Some(vec![("TZID".to_string(), vec![tzid.to_string()])])
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    #[test]
+    fn duration_minutes_hours_and_days() {
+        assert_eq!(duration("PT30M").unwrap(), Duration::minutes(30));
+        assert_eq!(duration("PT1H").unwrap(), Duration::hours(1));
+        assert_eq!(duration("P1D").unwrap(), Duration::days(1));
+    }
+
+    #[test]
+    fn duration_weeks_and_combined_forms() {
+        assert_eq!(duration("P2W").unwrap(), Duration::weeks(2));
+        assert_eq!(
+            duration("P1DT2H30M").unwrap(),
+            Duration::days(1) + Duration::hours(2) + Duration::minutes(30)
+        );
+        assert_eq!(
+            duration("PT1H30M15S").unwrap(),
+            Duration::seconds(3600 + 1800 + 15)
+        );
+        assert_eq!(duration("+P1D").unwrap(), Duration::days(1));
+    }
+
+    #[test]
+    fn duration_weeks_alone() {
+        for s in ["P1W2D", "P1WT2H", "P1W1W"] {
+            let error = duration(s).unwrap_err().to_string();
+            assert!(error.contains("Invalid DURATION"), "{s}: {error}");
+        }
+    }
+
+    #[test]
+    fn duration_negative() {
+        for s in ["-PT15M", "-P2W"] {
+            let error = duration(s).unwrap_err().to_string();
+            assert!(error.contains("cannot be negative"), "{s}: {error}");
+        }
+    }
+
+    #[test]
+    fn duration_malformed() {
+        for s in [
+            "1H", "PT", "P", "pt1h", "PT1h", "P1H", "PT1D", "P1DT", "PT30M1H", "P1D1D", "PTH", "",
+        ] {
+            let error = duration(s).unwrap_err().to_string();
+            assert!(error.contains("Invalid DURATION"), "{s}: {error}");
+        }
+    }
+
+    #[test]
+    fn naive_datetime_shapes() {
+        let expected = NaiveDate::from_ymd_opt(2025, 1, 1)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap();
+        for s in [
+            "20250101T090000",
+            "20250101T090000Z",
+            "20250101T0900",
+            "20250101T0900Z",
+        ] {
+            assert_eq!(naive_datetime(s, false).unwrap(), expected, "{s}");
+        }
+        assert_eq!(
+            naive_datetime("20250101", true).unwrap(),
+            NaiveDate::from_ymd_opt(2025, 1, 1)
+                .unwrap()
+                .and_time(NaiveTime::MIN)
+        );
+    }
+
+    #[test]
+    fn naive_datetime_t24_is_the_next_midnight() {
+        let midnight = NaiveDate::from_ymd_opt(2025, 1, 1)
+            .unwrap()
+            .and_time(NaiveTime::MIN);
+        for s in ["20241231T240000", "20241231T2400", "20241231T240000Z"] {
+            assert_eq!(naive_datetime(s, false).unwrap(), midnight, "{s}");
+        }
+        let leap_day = NaiveDate::from_ymd_opt(2024, 2, 29)
+            .unwrap()
+            .and_time(NaiveTime::MIN);
+        assert_eq!(naive_datetime("20240228T240000", false).unwrap(), leap_day);
+        assert!(naive_datetime("20250230T240000", false).is_err());
+    }
+
+    #[test]
+    fn naive_datetime_malformed() {
+        for s in [
+            "2025-01-01T09:00:00",
+            "20250101T09",
+            "20250101",
+            "garbage",
+            "",
+        ] {
+            let error = naive_datetime(s, false).unwrap_err().to_string();
+            assert!(error.contains("Invalid datetime"), "{s}: {error}");
+        }
+        assert!(naive_datetime("2025-01-01", true).is_err());
+    }
+
+    #[test]
+    fn z_suffix_is_utc() {
+        let timezones = TimeZones::default();
+        let z = datetime("20250707T070000Z", &None, &timezones).unwrap();
+        let tzid_utc = datetime("20250707T070000", &tzid("UTC"), &timezones).unwrap();
+        assert_eq!(z, tzid_utc);
+        assert_eq!(z, utc("2025-07-07T07:00:00Z"));
+        // The `Z` wins over a TZID.
+        let z_berlin = datetime("20250707T070000Z", &tzid("Europe/Berlin"), &timezones).unwrap();
+        assert_eq!(z_berlin, z);
+    }
+
+    #[test]
+    fn floating_time_in_the_local_timezone() {
+        let timezones = TimeZones::default().with_local(chrono_tz::Asia::Tokyo);
+        let floating = datetime("20250106T230000", &None, &timezones).unwrap();
+        assert_eq!(floating, utc("2025-01-06T14:00:00Z"));
+        assert_eq!(
+            zone_source("20250106T230000", &None, &timezones).to_string(),
+            "Local (Asia/Tokyo)"
+        );
+        // A TZID still wins.
+        let london = datetime("20250106T230000", &tzid("Europe/London"), &timezones).unwrap();
+        assert_eq!(london, utc("2025-01-06T23:00:00Z"));
+    }
+
+    #[test]
+    fn dst_gap_shifts_forward() {
+        // The clocks went from 01:00 to 02:00 on 2025-03-30.
+        let naive = naive_datetime("20250330T013000", false).unwrap();
+        let (dt, adjustment) = localize(naive, chrono_tz::Europe::London);
+        assert_eq!(dt, utc("2025-03-30T01:00:00Z"));
+        assert_eq!(adjustment, Some(DstAdjustment::Nonexistent(dt)));
+    }
+
+    #[test]
+    fn dst_overlap_picks_the_earlier_offset() {
+        // The clocks went from 02:00 back to 01:00 on 2025-10-26.
+        let naive = naive_datetime("20251026T013000", false).unwrap();
+        let (dt, adjustment) = localize(naive, chrono_tz::Europe::London);
+        assert_eq!(dt, utc("2025-10-26T00:30:00Z"));
+        assert_eq!(adjustment, Some(DstAdjustment::Ambiguous(dt)));
+    }
+
+    #[test]
+    fn dst_outside_transitions() {
+        let timezones = TimeZones::default();
+        let winter =
+            localized_datetime("20250115T090000", &tzid("Europe/London"), &timezones).unwrap();
+        assert_eq!(winter, (utc("2025-01-15T09:00:00Z"), None));
+        let summer = datetime("20250715T090000", &tzid("Europe/London"), &timezones).unwrap();
+        assert_eq!(summer, utc("2025-07-15T08:00:00Z"));
+    }
+
+    #[test]
+    fn text_unescapes() {
+        assert_eq!(text(r"Standup\, daily\; remote"), "Standup, daily; remote");
+        assert_eq!(text(r"Line 1\nLine 2\NLine 3"), "Line 1\nLine 2\nLine 3");
+        assert_eq!(text(r"C:\\Temp"), r"C:\Temp");
+        assert_eq!(text(r"trailing\"), r"trailing\");
+        assert_eq!(text("plain"), "plain");
+    }
+
+    #[test]
+    fn cal_address_normalizes() {
+        assert_eq!(cal_address("mailto:Ada@Example.com"), "ada@example.com");
+        assert_eq!(cal_address("MAILTO:ada@example.com"), "ada@example.com");
+        assert_eq!(cal_address(" ada@example.com "), "ada@example.com");
+        assert_eq!(cal_address("mail"), "mail");
+    }
+}
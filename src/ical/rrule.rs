@@ -1,16 +1,19 @@
 use crate::ical::parse;
+use crate::ical::tz::TimeZones;
 use anyhow::anyhow;
 use anyhow::Result;
 use chrono::DateTime;
 use chrono::Datelike;
+use chrono::Days;
 use chrono::Month;
+use chrono::NaiveDate;
 use chrono::Utc;
 use chrono::Weekday;
 use core::str;
 use std::str::FromStr;
 
 #[derive(Debug, Clone)]
-pub(crate) enum EventFrequency {
+pub enum EventFrequency {
     Secondly,
     Minutely,
     Hourly,
@@ -20,8 +23,10 @@ pub(crate) enum EventFrequency {
     Yearly,
 }
 
-impl EventFrequency {
-    pub(crate) fn from_str(s: &str) -> Result<Self> {
+impl FromStr for EventFrequency {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
         match s {
             "SECONDLY" => Ok(Self::Secondly),
             "MINUTELY" => Ok(Self::Minutely),
@@ -49,7 +54,8 @@ impl ByMonthDayDay {
                 .unwrap()
                 .num_days(dt.year())
                 .unwrap();
-            self.month_day.abs() as u8 == ((month_days - dt.day() as u8) / 7) + 1
+            // -1 is the last day of the month, -2 the second-to-last, etc.
+            self.month_day.unsigned_abs() == month_days - dt.day() as u8 + 1
         }
     }
 }
@@ -57,7 +63,7 @@ impl ByMonthDayDay {
 impl TryFrom<i8> for ByMonthDayDay {
     type Error = anyhow::Error;
     fn try_from(value: i8) -> std::result::Result<Self, Self::Error> {
-        if value > 31 || value < -31 || value == 0 {
+        if !(-31..=31).contains(&value) || value == 0 {
             return Err(anyhow!("Invalud BYMONTHDAY value: {}", value));
         }
         Ok(Self { month_day: value })
@@ -80,7 +86,8 @@ impl ByDayDay {
             n if n > 2 => {
                 let (n, wd) = s.split_at(s.len() - 2);
                 let n = n.parse::<i32>()?;
-                if n > 4 || n == 0 {
+                // A month has at most five of each weekday.
+                if !(-5..=5).contains(&n) || n == 0 {
                     Err(anyhow!("Invalid BYDAY. Unexpected week number '{}'.", n))
                 } else {
                     Ok(ByDayDay {
@@ -97,13 +104,13 @@ impl ByDayDay {
         if dt.weekday() == self.week_day {
             if let Some(n) = self.n {
                 if n > 0 {
-                    n.abs() as u8 == (dt.day() as u8 / 7) + 1
+                    n.unsigned_abs() as u8 == ((dt.day() as u8 - 1) / 7) + 1
                 } else {
                     let month_days = Month::try_from(dt.month() as u8)
                         .unwrap()
                         .num_days(dt.year())
                         .unwrap();
-                    n.abs() as u8 == ((month_days - dt.day() as u8) / 7) + 1
+                    n.unsigned_abs() as u8 == ((month_days - dt.day() as u8) / 7) + 1
                 }
             } else {
                 true
@@ -123,11 +130,8 @@ pub struct RRule {
     pub byday: Vec<ByDayDay>,
     pub bymonthday: Vec<ByMonthDayDay>,
 
-    #[allow(unused)]
     pub byweekno: Vec<i8>,
-    #[allow(unused)]
     pub bymonth: Vec<u8>,
-    #[allow(unused)]
     pub byyearday: Vec<i16>,
     #[allow(unused)]
     pub bysetpos: Vec<i16>,
@@ -138,11 +142,71 @@ const ORDYRNUM_MAX: u16 = 366;
 /// RRULE:FREQ=WEEKLY;WKST=MO;UNTIL=20250707T070000Z;INTERVAL=1;BYDAY=MO,TU,WE,TH,FR
 impl RRule {
     pub fn byday_matches(&self, dt: &DateTime<Utc>) -> bool {
-        self.byday.is_empty() || self.byday.iter().any(|d| d.matches(&dt))
+        self.byday.is_empty() || self.byday.iter().any(|d| d.matches(dt))
     }
 
     pub fn bymonthday_matches(&self, dt: &DateTime<Utc>) -> bool {
-        self.bymonthday.is_empty() || self.bymonthday.iter().any(|d| d.matches(&dt))
+        self.bymonthday.is_empty() || self.bymonthday.iter().any(|d| d.matches(dt))
+    }
+
+    pub fn bymonth_matches(&self, dt: &DateTime<Utc>) -> bool {
+        self.bymonth.is_empty() || self.bymonth.contains(&(dt.month() as u8))
+    }
+
+    /// Negative days count from the year end: -1 is December 31.
+    pub fn byyearday_matches(&self, dt: &DateTime<Utc>) -> bool {
+        if self.byyearday.is_empty() {
+            return true;
+        }
+        let day = dt.ordinal() as i16;
+        let year_days = if NaiveDate::from_ymd_opt(dt.year(), 2, 29).is_some() {
+            366
+        } else {
+            365
+        };
+        self.byyearday.iter().any(|n| {
+            if *n > 0 {
+                day == *n
+            } else {
+                day == year_days + n + 1
+            }
+        })
+    }
+
+    /// The first day of the week containing the `date`, weeks starting on WKST.
+    fn week_start_of(&self, date: NaiveDate) -> NaiveDate {
+        let days_since_start = (7 + date.weekday().num_days_from_monday()
+            - self.week_start.num_days_from_monday())
+            % 7;
+        date - Days::new(days_since_start as u64)
+    }
+
+    /// The year the week belongs to and the week number within that year.
+    ///
+    /// Follows ISO 8601 with weeks starting on WKST: week 1 is the first week
+    /// with at least four days in the year, so the last days of December may
+    /// belong to week 1 of the next year.
+    fn week_number(&self, date: NaiveDate) -> (i32, i64) {
+        let week_start = self.week_start_of(date);
+        let year = (week_start + Days::new(3)).year();
+        let first_week_start = self.week_start_of(NaiveDate::from_ymd_opt(year, 1, 4).unwrap());
+        (year, (week_start - first_week_start).num_days() / 7 + 1)
+    }
+
+    pub fn byweekno_matches(&self, dt: &DateTime<Utc>) -> bool {
+        if self.byweekno.is_empty() {
+            return true;
+        }
+        let (year, week) = self.week_number(dt.date_naive());
+        let (_, weeks_in_year) = self.week_number(NaiveDate::from_ymd_opt(year, 12, 28).unwrap());
+        self.byweekno.iter().any(|n| {
+            let n = *n as i64;
+            if n > 0 {
+                week == n
+            } else {
+                week == weeks_in_year + n + 1
+            }
+        })
     }
 
     fn parse_frequency(s: &str, frequency: &mut Option<EventFrequency>) -> Result<()> {
@@ -160,7 +224,12 @@ impl RRule {
         Ok(())
     }
 
-    fn parse_until(s: &str, until: &mut Option<DateTime<Utc>>) -> Result<()> {
+    fn parse_until(
+        s: &str,
+        zone: &Option<Vec<(String, Vec<String>)>>,
+        timezones: &TimeZones,
+        until: &mut Option<DateTime<Utc>>,
+    ) -> Result<()> {
         const NAME: &str = "UNTIL";
         if until.is_some() {
             return Err(anyhow!(
@@ -170,7 +239,8 @@ impl RRule {
             ));
         }
         *until = Some(
-            parse::datetime(s, &None).map_err(|e| anyhow!("Invalid {} '{}': {}", NAME, s, e))?,
+            parse::datetime(s, zone, timezones)
+                .map_err(|e| anyhow!("Invalid {} '{}': {}", NAME, s, e))?,
         );
         Ok(())
     }
@@ -285,7 +355,7 @@ impl RRule {
     ///
     /// - `s` - The string value associated with the BYMONTH key (e.g., "1,2,3").
     /// - `bymonth` - A mutable reference to a vector where the parsed month
-    ///    numbers (1-12) will be stored.
+    ///   numbers (1-12) will be stored.
     ///
     /// After the function returns, the `bymonth` array is sorted in ascending order.
     ///
@@ -322,7 +392,7 @@ impl RRule {
     }
 
     fn parse_bymonthday(s: &str, bymonthday: &mut Vec<ByMonthDayDay>) -> Result<()> {
-        const NAME: &str = "BYWEEKNO";
+        const NAME: &str = "BYMONTHDAY";
         if !bymonthday.is_empty() {
             return Err(anyhow!(
                 "Invalid RRULE: {} must not be set more than once: '{}'",
@@ -406,7 +476,25 @@ impl RRule {
         Ok(())
     }
 
-    pub(crate) fn from_str(s: &str) -> Result<Self> {
+    /// Parses the RRULE of an event whose DTSTART has the `dtstart_params`.
+    ///
+    /// A local-time UNTIL is in the timezone of DTSTART (RFC 5545 Section 3.3.10).
+    pub fn from_str_in_zone(
+        s: &str,
+        dtstart_params: &Option<Vec<(String, Vec<String>)>>,
+        timezones: &TimeZones,
+    ) -> Result<Self> {
+        let zone = dtstart_params.as_ref().map(|params| {
+            params
+                .iter()
+                .filter(|(name, _)| name.eq_ignore_ascii_case("TZID"))
+                .cloned()
+                .collect::<Vec<_>>()
+        });
+        // RRULE values never contain whitespace, but a partially unfolded
+        // line may leave some behind, e.g., `FREQ=WEEKLY; BYDAY=MO`.
+        let s = s.split_whitespace().collect::<String>();
+        let s = s.as_str();
         let mut frequency = None;
         let mut until = None;
         let mut count = None;
@@ -425,7 +513,7 @@ impl RRule {
             };
             match name.to_uppercase().as_str() {
                 "FREQ" => Self::parse_frequency(value, &mut frequency)?,
-                "UNTIL" => Self::parse_until(value, &mut until)?,
+                "UNTIL" => Self::parse_until(value, &zone, timezones, &mut until)?,
                 "COUNT" => Self::parse_count(value, &mut count)?,
                 "INTERVAL" => Self::parse_interval(value, &mut interval)?,
                 "WKST" => Self::parse_wkst(value, &mut week_start)?,
@@ -471,3 +559,190 @@ impl RRule {
         })
     }
 }
+
+impl FromStr for RRule {
+    type Err = anyhow::Error;
+
+    /// Parses an RRULE value, e.g., `FREQ=WEEKLY;BYDAY=MO,WE;UNTIL=20250131T000000Z`.
+    ///
+    /// A local-time UNTIL is in the local timezone; see
+    /// [`RRule::from_str_in_zone`] to give the timezone of DTSTART.
+    fn from_str(s: &str) -> Result<Self> {
+        Self::from_str_in_zone(s, &None, &TimeZones::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rrule(s: &str) -> RRule {
+        s.parse::<RRule>().unwrap()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn utc(y: i32, m: u32, d: u32) -> DateTime<Utc> {
+        date(y, m, d).and_hms_opt(9, 0, 0).unwrap().and_utc()
+    }
+
+    #[test]
+    fn week_number_across_the_year_boundary() {
+        let rrule = rrule("FREQ=YEARLY;BYWEEKNO=1");
+        // 2024-12-30 is a Monday; the week has four days in 2025.
+        assert_eq!(rrule.week_number(date(2024, 12, 29)), (2024, 52));
+        assert_eq!(rrule.week_number(date(2024, 12, 30)), (2025, 1));
+        assert_eq!(rrule.week_number(date(2024, 12, 31)), (2025, 1));
+        assert_eq!(rrule.week_number(date(2025, 1, 5)), (2025, 1));
+        assert_eq!(rrule.week_number(date(2025, 1, 6)), (2025, 2));
+        assert!(rrule.byweekno_matches(&utc(2024, 12, 31)));
+        assert!(!rrule.byweekno_matches(&utc(2024, 12, 29)));
+    }
+
+    #[test]
+    fn week_number_53() {
+        let week_53 = rrule("FREQ=YEARLY;BYWEEKNO=53");
+        // 2020 starts on a Wednesday, so it has 53 weeks.
+        assert_eq!(week_53.week_number(date(2020, 12, 31)), (2020, 53));
+        assert_eq!(week_53.week_number(date(2021, 1, 3)), (2020, 53));
+        assert_eq!(week_53.week_number(date(2021, 1, 4)), (2021, 1));
+        assert!(week_53.byweekno_matches(&utc(2021, 1, 1)));
+        // 2025 has 52 weeks: -1 is week 52.
+        assert!(!week_53.byweekno_matches(&utc(2025, 12, 28)));
+        let last_week = rrule("FREQ=YEARLY;BYWEEKNO=-1");
+        assert!(last_week.byweekno_matches(&utc(2025, 12, 28)));
+        assert!(last_week.byweekno_matches(&utc(2020, 12, 31)));
+    }
+
+    #[test]
+    fn week_number_honors_wkst() {
+        let rrule = rrule("FREQ=YEARLY;BYWEEKNO=1;WKST=SU");
+        // With weeks starting on Sunday, 2024-12-29 starts week 1 of 2025.
+        assert_eq!(rrule.week_number(date(2024, 12, 28)), (2024, 52));
+        assert_eq!(rrule.week_number(date(2024, 12, 29)), (2025, 1));
+        assert_eq!(rrule.week_number(date(2025, 1, 4)), (2025, 1));
+        assert_eq!(rrule.week_number(date(2025, 1, 5)), (2025, 2));
+    }
+
+    #[test]
+    fn negative_bymonthday_counts_from_the_month_end() {
+        let last = rrule("FREQ=MONTHLY;BYMONTHDAY=-1");
+        let second_to_last = rrule("FREQ=MONTHLY;BYMONTHDAY=-2");
+        // 28, 29, 30, and 31-day months.
+        for (y, m, days) in [(2025, 2, 28), (2024, 2, 29), (2025, 4, 30), (2025, 1, 31)] {
+            assert!(last.bymonthday_matches(&utc(y, m, days)), "{y}-{m}");
+            assert!(!last.bymonthday_matches(&utc(y, m, days - 1)), "{y}-{m}");
+            assert!(
+                second_to_last.bymonthday_matches(&utc(y, m, days - 1)),
+                "{y}-{m}"
+            );
+            assert!(
+                !second_to_last.bymonthday_matches(&utc(y, m, days)),
+                "{y}-{m}"
+            );
+        }
+        let first_of_31 = rrule("FREQ=MONTHLY;BYMONTHDAY=-31");
+        assert!(first_of_31.bymonthday_matches(&utc(2025, 1, 1)));
+        assert!(!first_of_31.bymonthday_matches(&utc(2025, 4, 1)));
+    }
+
+    #[test]
+    fn bymonthday_31_skips_shorter_months() {
+        let rrule = rrule("FREQ=MONTHLY;BYMONTHDAY=31");
+        assert!(rrule.bymonthday_matches(&utc(2025, 1, 31)));
+        assert!((1..=30).all(|day| !rrule.bymonthday_matches(&utc(2025, 4, day))));
+        assert!((1..=28).all(|day| !rrule.bymonthday_matches(&utc(2025, 2, day))));
+    }
+
+    #[test]
+    fn byday_nth_monday_of_a_31_day_month() {
+        // The Mondays of March 2025.
+        let mondays = [3, 10, 17, 24, 31];
+        for (i, day) in mondays.into_iter().enumerate() {
+            let nth = ByDayDay {
+                week_day: Weekday::Mon,
+                n: Some(i as i32 + 1),
+            };
+            let nth_to_last = ByDayDay {
+                week_day: Weekday::Mon,
+                n: Some(i as i32 - 5),
+            };
+            for other in mondays {
+                let dt = utc(2025, 3, other);
+                assert_eq!(nth.matches(&dt), other == day, "{nth:?} {dt}");
+                assert_eq!(
+                    nth_to_last.matches(&dt),
+                    other == day,
+                    "{nth_to_last:?} {dt}"
+                );
+            }
+            // Not a Monday.
+            assert!(!nth.matches(&utc(2025, 3, day - 1)));
+        }
+    }
+
+    #[test]
+    fn byday_ordinal_bounds() {
+        for byday in ["5MO", "-5FR", "+5TU"] {
+            let rule = rrule(&format!("FREQ=MONTHLY;BYDAY={byday}"));
+            assert_eq!(rule.byday.len(), 1, "{byday}");
+        }
+        for byday in ["6MO", "-6FR", "0MO"] {
+            let error = format!("FREQ=MONTHLY;BYDAY={byday}").parse::<RRule>();
+            assert!(error.is_err(), "{byday}");
+        }
+    }
+
+    #[test]
+    fn invalid_bymonthday_names_bymonthday() {
+        let error = "FREQ=MONTHLY;BYMONTHDAY=1x"
+            .parse::<RRule>()
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("BYMONTHDAY"), "{error}");
+        assert!(!error.contains("BYWEEKNO"), "{error}");
+        let error = "FREQ=YEARLY;BYWEEKNO=1x"
+            .parse::<RRule>()
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("BYWEEKNO"), "{error}");
+    }
+
+    #[test]
+    fn stray_spaces_are_ignored() {
+        let spaced = rrule(" FREQ = WEEKLY; INTERVAL=2 ;BYDAY= MO, WE ;COUNT=4 ");
+        let plain = rrule("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE;COUNT=4");
+        assert!(matches!(spaced.frequency, EventFrequency::Weekly));
+        assert_eq!(spaced.interval, plain.interval);
+        assert_eq!(spaced.count, plain.count);
+        assert_eq!(spaced.byday, plain.byday);
+    }
+
+    #[test]
+    fn byyearday_in_leap_and_common_years() {
+        let first = rrule("FREQ=YEARLY;BYYEARDAY=1");
+        let hundredth = rrule("FREQ=YEARLY;BYYEARDAY=100");
+        let last = rrule("FREQ=YEARLY;BYYEARDAY=-1");
+        for year in [2024, 2025] {
+            assert!(first.byyearday_matches(&utc(year, 1, 1)), "{year}");
+            assert!(!first.byyearday_matches(&utc(year, 1, 2)), "{year}");
+            assert!(last.byyearday_matches(&utc(year, 12, 31)), "{year}");
+            assert!(!last.byyearday_matches(&utc(year, 12, 30)), "{year}");
+        }
+        // Day 100 is April 9 in a leap year and April 10 otherwise.
+        assert!(hundredth.byyearday_matches(&utc(2024, 4, 9)));
+        assert!(hundredth.byyearday_matches(&utc(2025, 4, 10)));
+        assert!(!hundredth.byyearday_matches(&utc(2025, 4, 9)));
+        let day_366 = rrule("FREQ=YEARLY;BYYEARDAY=366");
+        assert!(day_366.byyearday_matches(&utc(2024, 12, 31)));
+        assert!(!day_366.byyearday_matches(&utc(2025, 12, 31)));
+        let last_366 = rrule("FREQ=YEARLY;BYYEARDAY=-366");
+        assert!(last_366.byyearday_matches(&utc(2024, 1, 1)));
+        assert!(!last_366.byyearday_matches(&utc(2025, 1, 1)));
+        let last_365 = rrule("FREQ=YEARLY;BYYEARDAY=-365");
+        assert!(last_365.byyearday_matches(&utc(2024, 1, 2)));
+        assert!(last_365.byyearday_matches(&utc(2025, 1, 1)));
+    }
+}
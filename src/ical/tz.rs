@@ -0,0 +1,427 @@
+use crate::ical::parse;
+use crate::ical::rrule::EventFrequency;
+use crate::ical::rrule::RRule;
+use anyhow::anyhow;
+use anyhow::Result;
+use chrono::Datelike;
+use chrono::FixedOffset;
+use chrono::NaiveDate;
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use ical::parser::ical::component::IcalTimeZone;
+use ical::parser::ical::component::IcalTimeZoneTransition;
+use std::collections::HashMap;
+
+/// Windows (Outlook/Exchange) timezone names mapped to IANA names.
+///
+/// Follows the default ("001") territory of the CLDR `windowsZones.xml`.
+const WINDOWS_TIMEZONES: &[(&str, &str)] = &[
+    ("Dateline Standard Time", "Etc/GMT+12"),
+    ("UTC-11", "Etc/GMT+11"),
+    ("Aleutian Standard Time", "America/Adak"),
+    ("Hawaiian Standard Time", "Pacific/Honolulu"),
+    ("Alaskan Standard Time", "America/Anchorage"),
+    ("Pacific Standard Time (Mexico)", "America/Tijuana"),
+    ("Pacific Standard Time", "America/Los_Angeles"),
+    ("US Mountain Standard Time", "America/Phoenix"),
+    ("Mountain Standard Time (Mexico)", "America/Mazatlan"),
+    ("Mountain Standard Time", "America/Denver"),
+    ("Central America Standard Time", "America/Guatemala"),
+    ("Central Standard Time", "America/Chicago"),
+    ("Central Standard Time (Mexico)", "America/Mexico_City"),
+    ("Canada Central Standard Time", "America/Regina"),
+    ("SA Pacific Standard Time", "America/Bogota"),
+    ("Eastern Standard Time (Mexico)", "America/Cancun"),
+    ("Eastern Standard Time", "America/New_York"),
+    ("US Eastern Standard Time", "America/Indianapolis"),
+    ("Venezuela Standard Time", "America/Caracas"),
+    ("Atlantic Standard Time", "America/Halifax"),
+    ("SA Western Standard Time", "America/La_Paz"),
+    ("Central Brazilian Standard Time", "America/Cuiaba"),
+    ("Pacific SA Standard Time", "America/Santiago"),
+    ("Newfoundland Standard Time", "America/St_Johns"),
+    ("E. South America Standard Time", "America/Sao_Paulo"),
+    ("SA Eastern Standard Time", "America/Cayenne"),
+    ("Argentina Standard Time", "America/Buenos_Aires"),
+    ("Greenland Standard Time", "America/Godthab"),
+    ("Montevideo Standard Time", "America/Montevideo"),
+    ("UTC-02", "Etc/GMT+2"),
+    ("Azores Standard Time", "Atlantic/Azores"),
+    ("Cape Verde Standard Time", "Atlantic/Cape_Verde"),
+    ("UTC", "Etc/UTC"),
+    ("GMT Standard Time", "Europe/London"),
+    ("Greenwich Standard Time", "Atlantic/Reykjavik"),
+    ("Morocco Standard Time", "Africa/Casablanca"),
+    ("W. Europe Standard Time", "Europe/Berlin"),
+    ("Central Europe Standard Time", "Europe/Budapest"),
+    ("Romance Standard Time", "Europe/Paris"),
+    ("Central European Standard Time", "Europe/Warsaw"),
+    ("W. Central Africa Standard Time", "Africa/Lagos"),
+    ("Jordan Standard Time", "Asia/Amman"),
+    ("GTB Standard Time", "Europe/Bucharest"),
+    ("Middle East Standard Time", "Asia/Beirut"),
+    ("Egypt Standard Time", "Africa/Cairo"),
+    ("E. Europe Standard Time", "Europe/Chisinau"),
+    ("Syria Standard Time", "Asia/Damascus"),
+    ("South Africa Standard Time", "Africa/Johannesburg"),
+    ("FLE Standard Time", "Europe/Kiev"),
+    ("Israel Standard Time", "Asia/Jerusalem"),
+    ("Kaliningrad Standard Time", "Europe/Kaliningrad"),
+    ("Arabic Standard Time", "Asia/Baghdad"),
+    ("Turkey Standard Time", "Europe/Istanbul"),
+    ("Arab Standard Time", "Asia/Riyadh"),
+    ("Belarus Standard Time", "Europe/Minsk"),
+    ("Russian Standard Time", "Europe/Moscow"),
+    ("E. Africa Standard Time", "Africa/Nairobi"),
+    ("Iran Standard Time", "Asia/Tehran"),
+    ("Arabian Standard Time", "Asia/Dubai"),
+    ("Azerbaijan Standard Time", "Asia/Baku"),
+    ("Georgian Standard Time", "Asia/Tbilisi"),
+    ("Caucasus Standard Time", "Asia/Yerevan"),
+    ("Afghanistan Standard Time", "Asia/Kabul"),
+    ("West Asia Standard Time", "Asia/Tashkent"),
+    ("Ekaterinburg Standard Time", "Asia/Yekaterinburg"),
+    ("Pakistan Standard Time", "Asia/Karachi"),
+    ("India Standard Time", "Asia/Calcutta"),
+    ("Sri Lanka Standard Time", "Asia/Colombo"),
+    ("Nepal Standard Time", "Asia/Katmandu"),
+    ("Central Asia Standard Time", "Asia/Almaty"),
+    ("Bangladesh Standard Time", "Asia/Dhaka"),
+    ("Omsk Standard Time", "Asia/Omsk"),
+    ("Myanmar Standard Time", "Asia/Rangoon"),
+    ("SE Asia Standard Time", "Asia/Bangkok"),
+    ("North Asia Standard Time", "Asia/Krasnoyarsk"),
+    ("China Standard Time", "Asia/Shanghai"),
+    ("North Asia East Standard Time", "Asia/Irkutsk"),
+    ("Singapore Standard Time", "Asia/Singapore"),
+    ("W. Australia Standard Time", "Australia/Perth"),
+    ("Taipei Standard Time", "Asia/Taipei"),
+    ("Ulaanbaatar Standard Time", "Asia/Ulaanbaatar"),
+    ("Tokyo Standard Time", "Asia/Tokyo"),
+    ("Korea Standard Time", "Asia/Seoul"),
+    ("Yakutsk Standard Time", "Asia/Yakutsk"),
+    ("Cen. Australia Standard Time", "Australia/Adelaide"),
+    ("AUS Central Standard Time", "Australia/Darwin"),
+    ("E. Australia Standard Time", "Australia/Brisbane"),
+    ("AUS Eastern Standard Time", "Australia/Sydney"),
+    ("West Pacific Standard Time", "Pacific/Port_Moresby"),
+    ("Tasmania Standard Time", "Australia/Hobart"),
+    ("Vladivostok Standard Time", "Asia/Vladivostok"),
+    ("Magadan Standard Time", "Asia/Magadan"),
+    ("Central Pacific Standard Time", "Pacific/Guadalcanal"),
+    ("New Zealand Standard Time", "Pacific/Auckland"),
+    ("UTC+12", "Etc/GMT-12"),
+    ("Fiji Standard Time", "Pacific/Fiji"),
+    ("Tonga Standard Time", "Pacific/Tongatapu"),
+    ("Samoa Standard Time", "Pacific/Apia"),
+    ("Line Islands Standard Time", "Pacific/Kiritimati"),
+];
+
+fn windows_to_iana(tzid: &str) -> Option<&'static str> {
+    WINDOWS_TIMEZONES
+        .iter()
+        .find(|(windows, _)| windows.eq_ignore_ascii_case(tzid))
+        .map(|(_, iana)| *iana)
+}
+
+/// Resolves a TZID given either as an IANA or as a Windows timezone name.
+pub fn timezone(tzid: &str) -> Result<Tz> {
+    if let Ok(tz) = tzid.parse::<Tz>() {
+        return Ok(tz);
+    }
+    windows_to_iana(tzid)
+        .and_then(|iana| iana.parse::<Tz>().ok())
+        .ok_or(anyhow!(
+            "Unknown TZID '{}': neither an IANA nor a Windows timezone name",
+            tzid
+        ))
+}
+
+/// A STANDARD or DAYLIGHT sub-component of a VTIMEZONE.
+#[derive(Debug, Clone)]
+struct Observance {
+    /// The first onset as the local time before the onset.
+    start: NaiveDateTime,
+    offset_from: FixedOffset,
+    offset_to: FixedOffset,
+    rrule: Option<RRule>,
+    rdates: Vec<NaiveDateTime>,
+}
+
+impl Observance {
+    fn parse(transition: &IcalTimeZoneTransition) -> Result<Self> {
+        let mut start = None;
+        let mut offset_from = None;
+        let mut offset_to = None;
+        let mut rrule = None;
+        let mut rdates = vec![];
+        for prop in transition.properties.iter() {
+            let value = prop.value.as_deref().unwrap_or_default();
+            match prop.name.as_str() {
+                "DTSTART" => start = Some(parse::naive_datetime(value, false)?),
+                "TZOFFSETFROM" => offset_from = Some(parse::utc_offset(value)?),
+                "TZOFFSETTO" => offset_to = Some(parse::utc_offset(value)?),
+                "RRULE" => rrule = Some(value.parse::<RRule>()?),
+                "RDATE" => {
+                    for rdate in value.split(',') {
+                        rdates.push(parse::naive_datetime(rdate, parse::is_date(&prop.params))?);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let offset_to = offset_to.ok_or(anyhow!("No TZOFFSETTO"))?;
+        Ok(Self {
+            start: start.ok_or(anyhow!("No DTSTART"))?,
+            offset_from: offset_from.unwrap_or(offset_to),
+            offset_to,
+            rrule,
+            rdates,
+        })
+    }
+
+    /// The last onset in the `year`, if any.
+    fn onset_in(&self, year: i32) -> Option<NaiveDateTime> {
+        let rdate = self
+            .rdates
+            .iter()
+            .chain([&self.start])
+            .filter(|dt| dt.year() == year)
+            .max()
+            .copied();
+        let Some(rrule) = &self.rrule else {
+            return rdate;
+        };
+        if year < self.start.year() || !matches!(rrule.frequency, EventFrequency::Yearly) {
+            return rdate;
+        }
+        let month = rrule
+            .bymonth
+            .first()
+            .map(|m| *m as u32)
+            .unwrap_or(self.start.month());
+        let onset = (1..=31)
+            .filter_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+            .map(|date| date.and_time(self.start.time()))
+            .find(|dt| {
+                let dt = dt.and_utc();
+                rrule.byday_matches(&dt) && rrule.bymonthday_matches(&dt)
+            })
+            .filter(|onset| *onset >= self.start)
+            .filter(|onset| {
+                rrule
+                    .until
+                    .map(|until| (*onset - self.offset_from).and_utc() <= until)
+                    .unwrap_or(true)
+            });
+        onset.max(rdate)
+    }
+}
+
+/// A timezone defined by a VTIMEZONE component of the calendar.
+#[derive(Debug, Clone)]
+pub struct VTimeZone {
+    observances: Vec<Observance>,
+}
+
+impl VTimeZone {
+    fn parse(timezone: &IcalTimeZone) -> Result<Self> {
+        let observances = timezone
+            .transitions
+            .iter()
+            .map(Observance::parse)
+            .collect::<Result<Vec<_>>>()?;
+        if observances.is_empty() {
+            return Err(anyhow!("No STANDARD or DAYLIGHT components"));
+        }
+        Ok(Self { observances })
+    }
+
+    /// The UTC offset in effect at the local time.
+    pub fn offset(&self, local: &NaiveDateTime) -> FixedOffset {
+        let latest = [local.year() - 1, local.year()]
+            .into_iter()
+            .flat_map(|year| {
+                self.observances
+                    .iter()
+                    .filter_map(move |o| o.onset_in(year).map(|onset| (onset, o)))
+            })
+            .filter(|(onset, _)| onset <= local)
+            .max_by_key(|(onset, _)| *onset);
+        match latest {
+            Some((_, observance)) => observance.offset_to,
+            None => {
+                let earliest = self.observances.iter().min_by_key(|o| o.start).unwrap();
+                earliest.offset_from
+            }
+        }
+    }
+}
+
+/// The VTIMEZONE definitions of a calendar by their TZID, and the timezone
+/// of the floating times, if not the system one.
+#[derive(Debug, Clone, Default)]
+pub struct TimeZones {
+    vtimezones: HashMap<String, VTimeZone>,
+    local: Option<Tz>,
+}
+
+impl TimeZones {
+    pub fn get(&self, tzid: &str) -> Option<&VTimeZone> {
+        self.vtimezones.get(tzid)
+    }
+
+    /// Resolves the floating times in `tz` instead of the system timezone.
+    pub fn with_local(self, tz: Tz) -> Self {
+        Self {
+            local: Some(tz),
+            ..self
+        }
+    }
+
+    /// The timezone of the floating times, if not the system one.
+    pub fn local(&self) -> Option<Tz> {
+        self.local
+    }
+}
+
+impl From<&Vec<IcalTimeZone>> for TimeZones {
+    fn from(timezones: &Vec<IcalTimeZone>) -> Self {
+        let mut table = HashMap::new();
+        for timezone in timezones {
+            let Some(tzid) = timezone
+                .properties
+                .iter()
+                .find(|p| p.name == "TZID")
+                .and_then(|p| p.value.clone())
+            else {
+                log::warn!("VTIMEZONE without TZID");
+                continue;
+            };
+            match VTimeZone::parse(timezone) {
+                Ok(vtimezone) => {
+                    table.insert(tzid, vtimezone);
+                }
+                Err(e) => log::warn!("Invalid VTIMEZONE '{tzid}': {e}"),
+            }
+        }
+        Self {
+            vtimezones: table,
+            ..Self::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ical::parse::datetime;
+    use chrono::DateTime;
+    use chrono::Utc;
+
+    fn tzid(tzid: &str) -> Option<Vec<(String, Vec<String>)>> {
+        Some(vec![("TZID".to_string(), vec![tzid.to_string()])])
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    #[test]
+    fn windows_names() {
+        assert_eq!(
+            timezone("W. Europe Standard Time").unwrap(),
+            Tz::Europe__Berlin
+        );
+        assert_eq!(
+            timezone("Eastern Standard Time").unwrap(),
+            Tz::America__New_York
+        );
+        assert_eq!(timezone("GMT Standard Time").unwrap(), Tz::Europe__London);
+        assert_eq!(timezone("Tokyo Standard Time").unwrap(), Tz::Asia__Tokyo);
+        assert_eq!(timezone("Europe/Paris").unwrap(), Tz::Europe__Paris);
+    }
+
+    #[test]
+    fn windows_names_resolve_to_utc() {
+        let timezones = TimeZones::default();
+        let cases = [
+            (
+                "W. Europe Standard Time",
+                "20250115T090000",
+                "2025-01-15T08:00:00Z",
+            ),
+            (
+                "W. Europe Standard Time",
+                "20250715T090000",
+                "2025-07-15T07:00:00Z",
+            ),
+            (
+                "Eastern Standard Time",
+                "20250115T090000",
+                "2025-01-15T14:00:00Z",
+            ),
+            (
+                "India Standard Time",
+                "20250115T090000",
+                "2025-01-15T03:30:00Z",
+            ),
+        ];
+        for (name, local, expected) in cases {
+            let dt = datetime(local, &tzid(name), &timezones).unwrap();
+            assert_eq!(dt, utc(expected), "{name} {local}");
+        }
+    }
+
+    #[test]
+    fn unknown_name_is_named_in_the_error() {
+        let error = timezone("Mars Standard Time").unwrap_err().to_string();
+        assert!(error.contains("'Mars Standard Time'"), "{error}");
+    }
+
+    #[test]
+    fn utc_offsets() {
+        assert_eq!(
+            parse::utc_offset("+0100").unwrap(),
+            FixedOffset::east_opt(3600).unwrap()
+        );
+        assert_eq!(
+            parse::utc_offset("-0530").unwrap(),
+            FixedOffset::west_opt(5 * 3600 + 30 * 60).unwrap()
+        );
+        assert_eq!(
+            parse::utc_offset("+013045").unwrap(),
+            FixedOffset::east_opt(3600 + 30 * 60 + 45).unwrap()
+        );
+        for s in ["0100", "+01", "+01:00", "+01000", "-01a0", ""] {
+            assert!(parse::utc_offset(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn vtimezone_fixture() {
+        let ics = include_str!("../../tests/fixtures/vtimezone.ics");
+        let calendar = ical::IcalParser::new(ics.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap();
+        let timezones = TimeZones::from(&calendar.timezones);
+        assert!(timezones.get("Office Time").is_some());
+        let starts = calendar
+            .events
+            .iter()
+            .map(|event| {
+                let dtstart = event
+                    .properties
+                    .iter()
+                    .find(|p| p.name == "DTSTART")
+                    .unwrap();
+                datetime(dtstart.value.as_ref().unwrap(), &dtstart.params, &timezones).unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            starts,
+            [utc("2025-01-15T08:00:00Z"), utc("2025-07-15T07:00:00Z")]
+        );
+    }
+}
@@ -0,0 +1,9 @@
+//! The iCalendar parsing and the recurrence expansion behind the `calvest`
+//! command-line tool.
+
+pub mod ical;
+
+pub use ical::Event;
+pub use ical::EventFrequency;
+pub use ical::EventIter;
+pub use ical::RRule;
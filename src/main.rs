@@ -1,22 +1,46 @@
 mod config;
 mod harvest;
-mod ical;
+mod output;
 
-use crate::config::Config;
-use crate::ical::Event;
-use ::ical::{parser::ical::component::IcalEvent, IcalParser};
+use crate::config::{
+    ClientBilling, Config, InputEncoding, NameStyle, OutputFormat, OverlapStrategy, SortOrder,
+};
+use crate::output::{JsonLinesWriter, JsonWriter, RecordWriter};
+use ::ical::{parser::ical::component::IcalEvent, property::Property, IcalParser};
 use anyhow::{anyhow, Result};
-use chrono::{NaiveDate, Utc};
-use ical::{StartDate, Summary};
-use std::collections::{HashMap, HashSet};
-use std::fs::{File, OpenOptions};
-use std::io::{self, BufReader, Write};
-use std::os::fd::{AsRawFd, FromRawFd};
+use calvest::ical::{self, Event, StartDate, Summary, TimeZones};
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use flate2::read::GzDecoder;
+use regex::Regex;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::LazyLock;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 struct Work {
     pub(crate) inner: harvest::Work,
     pub(crate) props: Vec<Option<String>>,
+    pub(crate) location: Option<String>,
+    pub(crate) categories: Vec<String>,
+    pub(crate) organizer: Option<String>,
+    /// The UID, suffixed with the start for an occurrence of a recurring event.
+    pub(crate) uid: String,
+    /// The SUMMARY as is, before any notes transformation.
+    pub(crate) summary: Option<String>,
+    pub(crate) status: Option<String>,
+    /// Whether a --task pattern matched the event summary.
+    pub(crate) task_matched: bool,
+    /// The rounding of the matched task pattern.
+    pub(crate) rounding: Option<(i64, harvest::RoundMode)>,
+    /// The notes template of the matched task pattern.
+    pub(crate) notes_template: Option<String>,
 }
 
 impl Work {
@@ -29,65 +53,296 @@ impl Work {
             config.last_name.clone(),
             config.default_task.clone(),
         );
-        work.start_datetime = Some(event.start_dt.clone());
-        work.end_datetime = Some(event.end_dt.clone());
+        work.start_datetime = Some(event.start_dt);
+        work.end_datetime = Some(event.end_dt);
+        if event.all_day {
+            let days = (event.end_dt - event.start_dt).num_days().max(1);
+            let minutes = (config.all_day_hours * 60.0).round() as i64;
+            work.duration = Some(chrono::Duration::minutes(minutes * days));
+        }
+        let mut location = None;
+        let mut categories = vec![];
+        let mut organizer = None;
         let mut attendeies = HashSet::new();
-        let accepted_state_name = "ACCEPTED".to_string();
+        let mut has_attendees = false;
+        let mut declined = false;
+        let mut cancelled = false;
+        let mut status = None;
         for prop in event.event.properties.iter() {
             match prop.name.as_str() {
                 "ORGANIZER" => {
+                    organizer = normalized_organizer(prop);
+                    has_attendees = true;
                     if !config.required_attendies.is_empty() {
-                        if let Some(value) = &prop.value {
-                            attendeies.insert(value.clone());
-                        }
+                        attendeies.extend(attendee_keys(prop));
                     }
                 }
                 "ATTENDEE" => {
+                    has_attendees = true;
+                    if let (Some(my_email), Some(value)) = (&config.my_email, &prop.value) {
+                        if ical::parse::cal_address(value) == *my_email
+                            && prop.params.iter().flatten().any(|(name, values)| {
+                                name == "PARTSTAT" && values.iter().any(|v| v == "DECLINED")
+                            })
+                        {
+                            declined = true;
+                        }
+                    }
                     if !config.required_attendies.is_empty() {
-                        if let Some(value) = &prop.value {
-                            if let Some(params) = &prop.params {
-                                if params
-                                    .iter()
-                                    .find(|p| {
-                                        p.0 == "PARTSTAT" && p.1.contains(&accepted_state_name)
-                                    })
-                                    .is_some()
-                                {
-                                    attendeies.insert(value.clone());
-                                }
+                        if let Some(params) = &prop.params {
+                            if params
+                                .iter()
+                                .find(|p| {
+                                    p.0 == "PARTSTAT"
+                                        && p.1.iter().any(|state| {
+                                            config.accepted_states.contains(&state.as_str())
+                                        })
+                                })
+                                .is_some()
+                            {
+                                attendeies.extend(attendee_keys(prop));
                             }
                         }
                     }
                 }
-                "SUMMARY" => work.notes = prop.value.clone(),
                 name => {
+                    if name == "LOCATION" {
+                        location = prop.value.clone();
+                    }
+                    if name == "CATEGORIES" {
+                        categories.extend(
+                            prop.value
+                                .iter()
+                                .flat_map(|value| value.split(','))
+                                .map(|category| ical::parse::text(category.trim())),
+                        );
+                    }
+                    if name == "STATUS" {
+                        status = prop.value.clone();
+                        cancelled = prop
+                            .value
+                            .as_deref()
+                            .is_some_and(|status| status.eq_ignore_ascii_case("CANCELLED"));
+                    }
                     if let Some(i) = config.extra_props.iter().position(|k| k.as_str() == name) {
                         props[i] = prop.value.clone();
                     }
                 }
             }
         }
-        if attendeies.is_empty() || config.required_attendies.is_subset(&attendeies) {
-            Ok(Some(Self { inner: work, props }))
+        work.notes = event_notes(&event.event, config).or(event.event.summary());
+        if declined || cancelled {
+            return Ok(None);
+        }
+        if !has_attendees || config.required_attendies.is_subset(&attendeies) {
+            Ok(Some(Self {
+                inner: work,
+                props,
+                location,
+                categories,
+                organizer,
+                uid: occurrence_uid(event),
+                summary: event.event.summary(),
+                status,
+                task_matched: false,
+                rounding: None,
+                notes_template: None,
+            }))
         } else {
             Ok(None)
         }
     }
 }
 
-fn log_work<IO: Write>(work: &Work, file: &mut csv::Writer<IO>) -> Result<()> {
+fn occurrence_uid(event: &Event) -> String {
+    match event.rrule {
+        Some(_) => format!("{}_{}", event.uid, event.start_dt.format("%Y%m%dT%H%M%SZ")),
+        None => event.uid.clone(),
+    }
+}
+
+/// The first non-empty of the --notes-from properties.
+fn event_notes(event: &IcalEvent, config: &Config) -> Option<String> {
+    config.notes_from.iter().find_map(|source| {
+        event
+            .properties
+            .iter()
+            .find(|p| p.name == source.property_name())
+            .and_then(|p| p.value.clone())
+            .filter(|value| !value.trim().is_empty())
+    })
+}
+
+/// The normalized email address and the lowercased CN (if any) of the
+/// attendee or organizer that a --required-attendee can match.
+fn attendee_keys(prop: &Property) -> Vec<String> {
+    let cn = prop
+        .params
+        .iter()
+        .flatten()
+        .find(|(n, _)| n.eq_ignore_ascii_case("CN"))
+        .and_then(|(_, v)| v.first())
+        .map(|cn| cn.trim_matches('"').to_lowercase())
+        .filter(|cn| !cn.is_empty());
+    prop.value
+        .as_deref()
+        .map(ical::parse::cal_address)
+        .into_iter()
+        .chain(cn)
+        .collect()
+}
+
+/// Formats the organizer as `CN <address>`, or as the bare address without CN.
+fn normalized_organizer(prop: &Property) -> Option<String> {
+    let address = ical::parse::cal_address(prop.value.as_deref()?);
+    let cn = prop
+        .params
+        .as_ref()
+        .and_then(|params| params.iter().find(|(n, _)| n.eq_ignore_ascii_case("CN")))
+        .and_then(|(_, v)| v.first())
+        .map(|cn| cn.trim_matches('"'))
+        .filter(|cn| !cn.is_empty());
+    Some(match cn {
+        Some(cn) => format!("{cn} <{address}>"),
+        None => address,
+    })
+}
+
+fn drop_notes_after(notes: &str, regex: &Regex) -> String {
+    notes
+        .lines()
+        .take_while(|line| !regex.is_match(line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+static NOTES_PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{(notes|summary|task|project|client)\}").unwrap());
+
+/// Replaces the `{notes}`, `{summary}`, `{task}`, `{project}`, and `{client}`
+/// placeholders of the template.
+fn templated_notes(template: &str, notes: &str, work: &Work) -> String {
+    let summary = work
+        .summary
+        .as_deref()
+        .map(ical::parse::text)
+        .unwrap_or_default();
+    let task = &work.inner.task;
+    NOTES_PLACEHOLDER
+        .replace_all(template, |captures: &regex::Captures| match &captures[1] {
+            "notes" => notes,
+            "summary" => summary.as_str(),
+            "task" => task.name.as_str(),
+            "project" => task.project.as_str(),
+            _ => task.client.as_str(),
+        })
+        .into_owned()
+}
+
+static NAME_PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{(first|last)\}").unwrap());
+
+fn combined_name(format: &str, first_name: &str, last_name: &str) -> String {
+    NAME_PLACEHOLDER
+        .replace_all(format, |captures: &regex::Captures| match &captures[1] {
+            "first" => first_name,
+            _ => last_name,
+        })
+        .into_owned()
+}
+
+/// The --client-billing terms of the work client.
+fn client_billing<'c>(work: &Work, config: &'c Config) -> Option<&'c ClientBilling> {
+    config
+        .client_billing
+        .iter()
+        .find(|billing| billing.client == work.inner.task.client)
+}
+
+/// The --client-billing rounding, or else the --round-to rounding, or else
+/// the rounding of the matched task pattern.
+fn rounding(work: &Work, config: &Config) -> Option<(i64, harvest::RoundMode)> {
+    client_billing(work, config)
+        .map(|billing| (billing.increment, harvest::RoundMode::Up))
+        .or(config
+            .round_to
+            .map(|increment| (increment, config.round_mode)))
+        .or(work.rounding)
+}
+
+/// The hours as written to the output, with the client billing terms applied.
+fn billed_hours(work: &Work, config: &Config) -> Option<String> {
+    work.inner.hours(
+        config.break_rule,
+        rounding(work, config),
+        client_billing(work, config).map(|billing| billing.minimum),
+        config.hours_format,
+    )
+}
+
+fn work_record(work: &Work, config: &Config) -> Result<Vec<String>> {
     let props = &work.props;
+    let organizer = config
+        .with_organizer
+        .then(|| work.organizer.as_deref().unwrap_or_default());
+    let uid = config.with_uid.then_some(work.uid.as_str());
+    let summary = config.with_summary.then(|| {
+        work.summary
+            .as_deref()
+            .map(ical::parse::text)
+            .unwrap_or_default()
+    });
+    let status = config
+        .with_status
+        .then(|| work.status.as_deref().unwrap_or_default());
+    let minutes = config
+        .with_minutes
+        .then(|| work.inner.minutes().unwrap_or(0).to_string());
+    let hours = billed_hours(work, config);
+    let notes_template = work
+        .notes_template
+        .as_deref()
+        .or(config.notes_template.as_deref())
+        .map(|template| |notes: &str| templated_notes(template, notes, work));
     let work = &work.inner;
-    let hours = work.hours();
     let hours = hours.unwrap_or("0".into());
-    let date = work.date_string();
+    let date = work.date_string(&config.date_format, config.timezone);
     let notes = work
         .notes
-        .as_ref()
-        .map(|s| s.replace(r"\,", ","))
-        .map(|s| s.replace(r"\;", ";"))
+        .as_deref()
+        .map(ical::parse::text)
         .unwrap_or("".into());
-    let required_values = vec![
+    let notes = match &config.drop_notes_after {
+        Some(regex) => drop_notes_after(&notes, regex),
+        None => notes,
+    };
+    let notes = if config.strip_notes.is_empty() {
+        notes
+    } else {
+        config
+            .strip_notes
+            .iter()
+            .fold(notes, |notes, regex| {
+                regex
+                    .replace_all(&notes, config.strip_notes_replacement.as_str())
+                    .into_owned()
+            })
+            .trim()
+            .to_string()
+    };
+    let notes = match notes_template {
+        Some(notes_template) => notes_template(&notes),
+        None => notes,
+    };
+    let name = match config.name_style {
+        NameStyle::Separate => None,
+        NameStyle::Combined => Some(combined_name(
+            &config.name_combine_format,
+            &work.first_name,
+            &work.last_name,
+        )),
+    };
+    let mut required_values = vec![
         date.as_ref()
             .ok_or(anyhow!("The work has no date\n{work:?}"))?
             .as_str(),
@@ -97,26 +352,195 @@ fn log_work<IO: Write>(work: &Work, file: &mut csv::Writer<IO>) -> Result<()> {
         work.task.name.as_str(),
         notes.as_str(),
         hours.as_str(),
-        work.first_name.as_str(),
-        work.last_name.as_str(),
     ];
+    match &name {
+        Some(name) => required_values.push(name.as_str()),
+        None => required_values.extend([work.first_name.as_str(), work.last_name.as_str()]),
+    }
     let empty_string = String::new();
-    let record = props
+    Ok(props
         .iter()
-        .map(|p| p.as_ref().unwrap_or_else(|| &empty_string).as_str())
-        .chain(required_values.into_iter())
-        .collect::<Vec<_>>();
-    file.write_record(&record)?;
+        .map(|p| p.as_ref().unwrap_or(&empty_string).as_str())
+        .chain(required_values)
+        .chain(organizer)
+        .chain(uid)
+        .chain(minutes.as_deref())
+        .chain(summary.as_deref())
+        .chain(status)
+        .map(String::from)
+        .collect())
+}
+
+fn log_work(work: &Work, config: &Config, writers: &mut [Box<dyn RecordWriter>]) -> Result<()> {
+    let record = work_record(work, config)?;
+    let record = record.iter().map(String::as_str).collect::<Vec<_>>();
+    for writer in writers.iter_mut() {
+        writer.write_record(&record)?;
+    }
     //eprintln!("{record:?}");
     Ok(())
 }
 
+/// Warns about the records violating the Harvest import format rules and
+/// returns the number of the violations.
+fn validate_harvest(works: &[Work], column_names: &[&str], config: &Config) -> Result<usize> {
+    let mut violations = 0;
+    for (i, work) in works.iter().enumerate() {
+        let record = work_record(work, config)?;
+        let record = record.iter().map(String::as_str).collect::<Vec<_>>();
+        for violation in harvest::validate_record(column_names, &record) {
+            log::warn!("Row {}: {violation}. Row: {record:?}", i + 1);
+            violations += 1;
+        }
+    }
+    Ok(violations)
+}
+
+fn print_preview(works: &[Work], column_names: &[&str], config: &Config) -> Result<()> {
+    let count = config.preview_count.unwrap_or(works.len()).min(works.len());
+    let mut rows = vec![column_names
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()];
+    for work in works[..count].iter() {
+        let record = work_record(work, config)?;
+        rows.push(record.into_iter().map(|v| v.replace('\n', " ")).collect());
+    }
+    let mut widths = vec![0; column_names.len()];
+    for row in rows.iter() {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+    for row in rows.iter() {
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(value, width)| format!("{value:width$}"))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        println!("{}", line.trim_end());
+    }
+    if count < works.len() {
+        println!("... and {} more", works.len() - count);
+    }
+    Ok(())
+}
+
+/// The hours as logged, so that the totals match the output.
+fn work_hours(work: &Work, config: &Config) -> f64 {
+    billed_hours(work, config)
+        .and_then(|hours| hours.parse::<f64>().ok())
+        .unwrap_or(0.0)
+}
+
+/// Prints the total hours per client, project, and task to stderr.
+fn print_summary(works: &[Work], config: &Config) {
+    let mut totals: BTreeMap<(&str, &str, &str), f64> = BTreeMap::new();
+    for work in works.iter() {
+        let hours = work_hours(work, config);
+        let task = &work.inner.task;
+        let key = (
+            task.client.as_str(),
+            task.project.as_str(),
+            task.name.as_str(),
+        );
+        *totals.entry(key).or_default() += hours;
+    }
+    let total = totals.values().sum::<f64>();
+    let mut rows = vec![["Client", "Project", "Task", "Hours"].map(String::from)];
+    for ((client, project, task), hours) in totals {
+        rows.push([client, project, task, &format!("{hours:.2}")].map(String::from));
+    }
+    rows.push(["Total", "", "", &format!("{total:.2}")].map(String::from));
+    let mut widths = [0; 4];
+    for row in rows.iter() {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+    eprintln!();
+    for [client, project, task, hours] in rows.iter() {
+        let [client_width, project_width, task_width, hours_width] = widths;
+        eprintln!(
+            "{client:client_width$} | {project:project_width$} | {task:task_width$} | {hours:>hours_width$}"
+        );
+    }
+    // More hours than the window has likely means double counting, e.g., an
+    // all-day event or overlapping duplicates.
+    if let Some(start_dt) = config.start_date {
+        let end_dt = config.end_date.unwrap_or_else(Utc::now);
+        let window_hours = (end_dt - start_dt).num_minutes() as f64 / 60.0;
+        if total > window_hours {
+            log::warn!(
+                "The total of {total:.2} hours exceeds the {window_hours:.2} hours of the time window"
+            );
+        }
+    }
+}
+
+/// Prints the summaries that matched no --task pattern, the most time-consuming first.
+fn print_task_suggestions(works: &[Work], config: &Config) {
+    let mut totals: HashMap<String, (usize, f64)> = HashMap::new();
+    for work in works.iter().filter(|work| !work.task_matched) {
+        let hours = work_hours(work, config);
+        let summary = ical::parse::text(work.inner.notes.as_deref().unwrap_or_default());
+        let (count, total) = totals.entry(summary).or_default();
+        *count += 1;
+        *total += hours;
+    }
+    let mut totals = totals.into_iter().collect::<Vec<_>>();
+    totals.sort_by(|(l_summary, (_, l)), (r_summary, (_, r))| {
+        r.total_cmp(l).then(l_summary.cmp(r_summary))
+    });
+    let mut rows = vec![["Hours", "Count", "Summary"].map(String::from)];
+    for (summary, (count, hours)) in totals {
+        rows.push([
+            format!("{hours:.2}"),
+            count.to_string(),
+            summary.replace('\n', " "),
+        ]);
+    }
+    let mut widths = [0; 2];
+    for row in rows.iter() {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+    let [hours_width, count_width] = widths;
+    for [hours, count, summary] in rows.iter() {
+        println!("{hours:>hours_width$} | {count:>count_width$} | {summary}");
+    }
+}
+
+/// Runs the --post-hook command with the output path as its argument and
+/// the run stats in the environment.
+fn run_post_hook(command: &str, works: &[Work], config: &Config) -> Result<()> {
+    let hours = works
+        .iter()
+        .map(|work| work_hours(work, config))
+        .sum::<f64>();
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{command} \"$@\""))
+        .arg("sh")
+        .args(config.output.iter())
+        .env("CALVEST_EVENTS", works.len().to_string())
+        .env("CALVEST_HOURS", format!("{hours:.2}"))
+        .status()
+        .map_err(|e| anyhow!("Cannot run the post hook '{command}'\n{e}"))?;
+    if !status.success() {
+        return Err(anyhow!("The post hook '{command}' failed: {status}"));
+    }
+    Ok(())
+}
+
 fn announce_event_collection(config: &Config) {
     let start_date = &config
         .start_date
         .map(|dt| {
             " from ".to_string()
-                + &dt.with_timezone(&chrono::Local).date_naive().to_string()
+                + &dt.with_timezone(&config.timezone).date_naive().to_string()
                 + " (inclusive)"
         })
         .unwrap_or("".into());
@@ -124,144 +548,1910 @@ fn announce_event_collection(config: &Config) {
         .end_date
         .map(|dt| {
             " to ".to_string()
-                + &dt.with_timezone(&chrono::Local).date_naive().to_string()
+                + &dt.with_timezone(&config.timezone).date_naive().to_string()
                 + " (exclusive)"
         })
         .unwrap_or("".into());
 
-    eprintln!("Collecting events{start_date}{end_date} ...");
+    log::info!("Collecting events{start_date}{end_date} ...");
+}
+
+fn decode(bytes: Vec<u8>, encoding: InputEncoding) -> String {
+    let from_windows_1252 = |bytes: &[u8]| {
+        encoding_rs::WINDOWS_1252
+            .decode_without_bom_handling(bytes)
+            .0
+            .into_owned()
+    };
+    match encoding {
+        InputEncoding::Utf8 => String::from_utf8_lossy(&bytes).into_owned(),
+        InputEncoding::Latin1 => from_windows_1252(&bytes),
+        InputEncoding::Auto => {
+            String::from_utf8(bytes).unwrap_or_else(|e| from_windows_1252(e.as_bytes()))
+        }
+    }
+}
+
+/// Wraps the bare VEVENTs, e.g., pasted from a calendar, into a VCALENDAR.
+fn wrap_bare_events(ics: String) -> String {
+    let upper = ics.to_uppercase();
+    if upper.contains("BEGIN:VCALENDAR") || !upper.contains("BEGIN:VEVENT") {
+        return ics;
+    }
+    log::warn!("No VCALENDAR in the input; reading the bare VEVENTs");
+    format!(
+        "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{}\r\nEND:VCALENDAR\r\n",
+        ics.trim_end()
+    )
 }
 
-fn open_ical_reader(config: &Config) -> Result<IcalParser<BufReader<File>>> {
-    let file = if let Some(path) = config.input.as_ref() {
-        File::open(path.clone())
-            .map_err(|e| anyhow!("Cannot open the intput file {path:?}\n{e}"))?
+/// Decompresses the input if it starts with the gzip magic bytes, e.g., an
+/// `.ics.gz` export.
+fn gunzip(input: Box<dyn Read>) -> Result<Box<dyn Read>> {
+    let mut input = BufReader::new(input);
+    let gzipped = input
+        .fill_buf()
+        .map_err(|e| anyhow!("Cannot read the input\n{e}"))?
+        .starts_with(&[0x1f, 0x8b]);
+    Ok(if gzipped {
+        Box::new(GzDecoder::new(input))
     } else {
-        unsafe { File::from_raw_fd(io::stdin().as_raw_fd()) }
+        Box::new(input)
+    })
+}
+
+fn ical_reader(input: Box<dyn Read>, config: &Config) -> Result<Box<dyn BufRead>> {
+    let input = gunzip(input)?;
+    Ok(match (config.input_encoding, config.lenient_input) {
+        (InputEncoding::Utf8, false) => Box::new(BufReader::new(input)),
+        (encoding, lenient_input) => {
+            let mut bytes = vec![];
+            BufReader::new(input)
+                .read_to_end(&mut bytes)
+                .map_err(|e| anyhow!("Cannot read the input\n{e}"))?;
+            let ics = decode(bytes, encoding);
+            let ics = if lenient_input {
+                wrap_bare_events(ics)
+            } else {
+                ics
+            };
+            Box::new(Cursor::new(ics.into_bytes()))
+        }
+    })
+}
+
+fn is_url(input: &str) -> bool {
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+/// Streams the body of the http(s) response, e.g., a calendar subscription.
+fn fetch(url: &str, config: &Config) -> Result<Box<dyn Read>> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(config.http_timeout))
+        .build();
+    let request = config
+        .headers
+        .iter()
+        .fold(agent.get(url), |request, (name, value)| {
+            request.set(name, value)
+        });
+    let response = request
+        .call()
+        .map_err(|e| anyhow!("Cannot fetch the input {url}\n{e}"))?;
+    Ok(Box::new(response.into_reader()))
+}
+
+fn open_ical_readers(config: &Config) -> Result<Vec<IcalParser<Box<dyn BufRead>>>> {
+    let inputs: Vec<Box<dyn Read>> = if config.input.is_empty() {
+        vec![Box::new(io::stdin().lock())]
+    } else {
+        config
+            .input
+            .iter()
+            .map(|path| match path.to_str().filter(|path| is_url(path)) {
+                Some(url) => fetch(url, config),
+                None => File::open(path)
+                    .map(|file| Box::new(file) as Box<dyn Read>)
+                    .map_err(|e| anyhow!("Cannot open the intput file {path:?}\n{e}")),
+            })
+            .collect::<Result<Vec<_>>>()?
     };
 
-    let file_reader = BufReader::new(file);
-    Ok(IcalParser::new(file_reader))
+    inputs
+        .into_iter()
+        .map(|input| Ok(IcalParser::new(ical_reader(input, config)?)))
+        .collect()
+}
+
+/// The output files, written to `<path>.tmp` until [`PendingOutputs::commit`]
+/// renames them, so a failed run leaves the previous output intact.
+///
+/// The path is resolved first, so the temporary file is created next to the
+/// target of a symlink, and the rename replaces the target, not the symlink.
+/// Anything but a regular file, e.g., `/dev/stdout` or a FIFO, is written
+/// directly.
+#[derive(Default)]
+struct PendingOutputs {
+    /// The resolved paths of the outputs to rename on commit.
+    paths: Vec<PathBuf>,
 }
 
-fn open_csv_writer(config: &Config) -> Result<csv::Writer<File>> {
-    let file = if let Some(path) = &config.output {
-        OpenOptions::new()
+impl PendingOutputs {
+    fn tmp_path(path: &Path) -> PathBuf {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        tmp_path.into()
+    }
+
+    /// The path with the symlinks resolved, also when the file does not
+    /// exist yet.
+    fn resolve(path: &Path) -> PathBuf {
+        if let Ok(path) = fs::canonicalize(path) {
+            return path;
+        }
+        let parent = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty());
+        match (parent.map(fs::canonicalize), path.file_name()) {
+            (Some(Ok(parent)), Some(name)) => parent.join(name),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    fn create(&mut self, path: &Path) -> Result<File> {
+        let path = Self::resolve(path);
+        let is_regular_file = fs::metadata(&path)
+            .map(|metadata| metadata.is_file())
+            .unwrap_or(true);
+        if !is_regular_file {
+            return OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .map_err(|e| anyhow!("Cannot open the output file {path:?}\n{e}"));
+        }
+        let tmp_path = Self::tmp_path(&path);
+        let file = OpenOptions::new()
             .write(true)
             .create(true)
             .truncate(true)
             .append(false)
-            .open(path.clone())
-            .map_err(|e| anyhow!("Cannot open the output file {path:?}\n{e}"))?
+            .open(&tmp_path)
+            .map_err(|e| anyhow!("Cannot open the output file {tmp_path:?}\n{e}"))?;
+        self.paths.push(path);
+        Ok(file)
+    }
+
+    fn commit(mut self) -> Result<()> {
+        for path in std::mem::take(&mut self.paths) {
+            fs::rename(Self::tmp_path(&path), &path)
+                .map_err(|e| anyhow!("Cannot write the output file {path:?}\n{e}"))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PendingOutputs {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = fs::remove_file(Self::tmp_path(path));
+        }
+    }
+}
+
+fn open_writer(config: &Config, outputs: &mut PendingOutputs) -> Result<Box<dyn RecordWriter>> {
+    let file: Box<dyn Write> = if let Some(path) = &config.output {
+        Box::new(outputs.create(path)?)
     } else {
-        unsafe { File::from_raw_fd(io::stdout().as_raw_fd()) }
+        Box::new(io::stdout().lock())
     };
 
-    Ok(csv::WriterBuilder::new().from_writer(file))
+    Ok(match config.format {
+        OutputFormat::Csv => Box::new(csv::WriterBuilder::new().from_writer(file)),
+        OutputFormat::Json => Box::new(JsonWriter::new(BufWriter::new(file))),
+        OutputFormat::Jsonl => Box::new(JsonLinesWriter::new(BufWriter::new(file))),
+    })
+}
+
+fn open_writers(
+    config: &Config,
+    outputs: &mut PendingOutputs,
+) -> Result<Vec<Box<dyn RecordWriter>>> {
+    let mut writers = vec![];
+    // With --split-by-month alone, nothing goes to <stdout>.
+    if config.output.is_some() || config.split_by_month.is_none() {
+        writers.push(open_writer(config, outputs)?);
+    }
+    if let Some(path) = &config.also_json {
+        let file = BufWriter::new(outputs.create(path)?);
+        writers.push(Box::new(JsonWriter::new(file)));
+    }
+    if let Some(path) = &config.also_jsonl {
+        let file = BufWriter::new(outputs.create(path)?);
+        writers.push(Box::new(JsonLinesWriter::new(file)));
+    }
+    Ok(writers)
+}
+
+/// Writes the works into a CSV file per month of the Date column.
+fn write_months(
+    dir: &Path,
+    works: &[Work],
+    column_names: &[&str],
+    config: &Config,
+    outputs: &mut PendingOutputs,
+) -> Result<()> {
+    let mut months: BTreeMap<String, Vec<&Work>> = BTreeMap::new();
+    for work in works.iter() {
+        let month = work
+            .inner
+            .start_datetime
+            .map(|dt| {
+                dt.with_timezone(&config.timezone)
+                    .format("%Y-%m")
+                    .to_string()
+            })
+            .ok_or(anyhow!("The work has no date\n{:?}", work.inner))?;
+        months.entry(month).or_default().push(work);
+    }
+    fs::create_dir_all(dir).map_err(|e| anyhow!("Cannot create the directory {dir:?}\n{e}"))?;
+    for (month, works) in months {
+        let path = dir.join(format!("{month}.csv"));
+        let mut writer = csv::WriterBuilder::new().from_writer(outputs.create(&path)?);
+        writer.write_record(column_names)?;
+        for work in works.iter() {
+            writer.write_record(work_record(work, config)?)?;
+        }
+        writer
+            .flush()
+            .map_err(|e| anyhow!("Cannot write to the output file {path:?}\n{e}"))?;
+        log::info!("Wrote {path:?} with {} work entries", works.len());
+    }
+    Ok(())
+}
+
+type PropertyKey = (String, Option<Vec<(String, Vec<String>)>>, Option<String>);
+
+/// Identifies a VEVENT block by all of its properties.
+fn event_block_key(event: &IcalEvent) -> Vec<PropertyKey> {
+    event
+        .properties
+        .iter()
+        .map(|p| (p.name.clone(), p.params.clone(), p.value.clone()))
+        .collect()
 }
 
-fn relevant_events(event: &IcalEvent, config: &Config) -> Result<Vec<Event>> {
+fn relevant_events(
+    event: &IcalEvent,
+    timezones: &TimeZones,
+    config: &Config,
+) -> Result<Vec<Event>> {
     //eprintln!();
-    let Some(_summary) = event.summary() else {
-        eprintln!("WARN: No SUMMARY; start date: {:?}", event.start_date());
+    if event.summary().is_none() && event_notes(event, config).is_none() {
+        log::warn!("No SUMMARY; start date: {:?}", event.start_date());
         return Ok(vec![]);
-    };
+    }
     //eprintln!("Processing event: {}", summary.value.as_ref().unwrap());
-    let event = match Event::try_from(event.clone()) {
-        Ok(event) => event,
-        Err(error) => {
-            eprintln!("WARN: {error}");
-            return Ok(vec![]);
-        }
-    };
+    let mut event = event.clone();
+    if config.ignore_rrule {
+        event.properties.retain(|p| p.name != "RRULE");
+    }
+    let mut event = Event::new(event, timezones)?;
+    if config.verbosity >= 2 {
+        let dtstart = event
+            .event
+            .properties
+            .iter()
+            .find(|p| p.name == "DTSTART")
+            .and_then(|p| p.value.as_deref())
+            .unwrap_or_default();
+        log::debug!(
+            "DTSTART {dtstart} in {} is {} UTC. Event: UID={:?} SUMMARY={:?}",
+            event.start_zone,
+            event.start_dt.format("%Y-%m-%d %H:%M:%S"),
+            event.uid,
+            event.event.summary()
+        );
+    }
+    if event.open_ended && !config.infer_end_from_next {
+        let Some(hours) = config.default_hours else {
+            return Err(anyhow!(
+                "Unsupported event: no DTEND or DURATION. Event: UID={:?} DTSTART={:?}",
+                event.uid,
+                event.start_dt
+            ));
+        };
+        event.end_dt = event.start_dt + chrono::Duration::minutes((hours * 60.0).round() as i64);
+        event.open_ended = false;
+    }
     //eprintln!("  rrule: {:?}", event.rrule);
-    let until_date = config.end_date.unwrap_or(Utc::now());
-    let from_date = &config.start_date;
+    let now = Utc::now();
+    let until_date = config.end_date.unwrap_or(now);
+    let from_date = config.start_date.unwrap_or(DateTime::<Utc>::MIN_UTC);
     Ok(event
-        .recurring()
-        .skip_while(|event| from_date.map(|sdt| event.start_dt < sdt).unwrap_or(false))
-        .take_while(|event| event.start_dt < until_date)
+        .occurrences_between(from_date, until_date)
+        .into_iter()
+        .filter(|event| !config.only_past || event.start_dt < now)
+        .filter(|event| !config.only_future || event.start_dt >= now)
         .collect())
 }
 
 fn event_to_work(event: &Event, config: &Config) -> Result<Option<Work>> {
     let patterns = &config.tasks;
-    let Some(mut work) = Work::from_event(&event, &config)? else {
+    let Some(mut work) = Work::from_event(event, config)? else {
         return Ok(None);
     };
-    if let Some(pattern) = patterns
+    if let Some(min_duration) = config.min_duration {
+        if work.inner.minutes().unwrap_or(0) < min_duration {
+            return Ok(None);
+        }
+    }
+    let summary = work.inner.notes.as_deref().unwrap_or_default();
+    if config.exclude_patterns.iter().any(|p| p.is_match(summary)) {
+        return Ok(None);
+    }
+    if config.skip_decorative && !summary.chars().any(char::is_alphanumeric) {
+        return Ok(None);
+    }
+    let location = work.location.as_deref();
+    if let Some(regex) = &config.location {
+        if !location.map(|l| regex.is_match(l)).unwrap_or(false) {
+            return Ok(None);
+        }
+    }
+    if let Some(regex) = &config.exclude_location {
+        if location.map(|l| regex.is_match(l)).unwrap_or(false) {
+            return Ok(None);
+        }
+    }
+    let category_task = config.category_tasks.iter().find(|c| {
+        work.categories
+            .iter()
+            .any(|category| category.eq_ignore_ascii_case(&c.category))
+    });
+    if let Some(category_task) = category_task {
+        work.inner.task = category_task.task.clone();
+        work.task_matched = true;
+    } else if let Some(pattern) = patterns.iter().find(|p| {
+        work.inner
+            .notes
+            .as_ref()
+            .map(|s| p.regex.is_match(s))
+            .unwrap_or(false)
+    }) {
+        if !pattern.billable {
+            log::info!(
+                "Skipped the non-billable task '{}': {:?} on {}",
+                pattern.task.name,
+                work.inner.notes.as_deref().unwrap_or_default(),
+                event
+                    .start_dt
+                    .with_timezone(&config.timezone)
+                    .format("%Y-%m-%d %H:%M")
+            );
+            return Ok(None);
+        }
+        work.inner.task = pattern.task.clone();
+        work.task_matched = true;
+        work.rounding = pattern.rounding;
+        work.notes_template = pattern.notes_template.clone();
+        if let Some(hours) = pattern.hours {
+            work.inner.duration = Some(chrono::Duration::minutes((hours * 60.0).round() as i64));
+        }
+    }
+    let summary = work.inner.notes.as_deref().unwrap_or_default();
+    if let Some((_, hours)) = config
+        .task_hours
         .iter()
-        .filter(|p| {
-            work.inner
-                .notes
-                .as_ref()
-                .map(|s| p.regex.is_match(&s))
-                .unwrap_or(false)
-        })
-        .next()
+        .find(|(regex, _)| regex.is_match(summary))
     {
-        work.inner.task = pattern.task.clone();
+        work.inner.duration = Some(chrono::Duration::minutes((hours * 60.0).round() as i64));
     }
     Ok(Some(work))
 }
 
+/// Ends each open-ended event at the start of the next event, but no later
+/// than `max_inferred_hours` after its own start. The last one lasts
+/// `default_hours`, if set, or else `max_inferred_hours`.
+fn infer_end_from_next(events: &mut [Event], config: &Config) {
+    let minutes = |hours: f64| chrono::Duration::minutes((hours * 60.0).round() as i64);
+    let max_duration = minutes(config.max_inferred_hours);
+    let last_duration = minutes(config.default_hours.unwrap_or(config.max_inferred_hours));
+    events.sort_by_key(|event| event.start_dt);
+    for i in 0..events.len() {
+        if !events[i].open_ended {
+            continue;
+        }
+        let start_dt = events[i].start_dt;
+        let max_end_dt = start_dt + max_duration;
+        let end_dt = events[i + 1..]
+            .iter()
+            .map(|event| event.start_dt)
+            .find(|next_start_dt| *next_start_dt > start_dt)
+            .map(|next_start_dt| next_start_dt.min(max_end_dt))
+            .unwrap_or(start_dt + last_duration);
+        events[i].end_dt = end_dt;
+    }
+}
+
+/// Warns about each pair of overlapping events of different UIDs and returns
+/// the number of the pairs.
+fn report_conflicts(events: &[Event]) -> usize {
+    let mut events = events
+        .iter()
+        .filter(|event| !event.all_day && event.end_dt > event.start_dt)
+        .collect::<Vec<_>>();
+    events.sort_by_key(|event| event.start_dt);
+    let describe = |event: &Event| {
+        format!(
+            "UID={} SUMMARY={:?} {} - {}",
+            event.uid,
+            event.event.summary().unwrap_or_default(),
+            event.start_dt,
+            event.end_dt
+        )
+    };
+    let mut conflicts = 0;
+    for (i, event) in events.iter().enumerate() {
+        for other in events[i + 1..]
+            .iter()
+            .take_while(|other| other.start_dt < event.end_dt)
+        {
+            if other.dedup_key() != event.dedup_key() {
+                log::warn!("Conflict: {} overlaps {}", describe(event), describe(other));
+                conflicts += 1;
+            }
+        }
+    }
+    conflicts
+}
+
+/// Splits the work at the midnights of the Date column in the `tz` timezone
+/// into a work per day. An overridden duration is shared in proportion to the
+/// time on each day.
+fn split_multiday(work: Work, tz: Tz) -> Vec<Work> {
+    let (Some(start_dt), Some(end_dt)) = (work.inner.start_datetime, work.inner.end_datetime)
+    else {
+        return vec![work];
+    };
+    let span = (end_dt - start_dt).num_seconds();
+    let mut works = vec![];
+    let mut day_start_dt = start_dt;
+    while day_start_dt < end_dt {
+        let date = day_start_dt.with_timezone(&tz).date_naive();
+        let next_midnight = config::midnight(date + chrono::Days::new(1), tz).to_utc();
+        let day_end_dt = next_midnight.min(end_dt);
+        let mut day_work = work.clone();
+        day_work.inner.start_datetime = Some(day_start_dt);
+        day_work.inner.end_datetime = Some(day_end_dt);
+        day_work.inner.duration = work.inner.duration.map(|duration| {
+            let seconds = (day_end_dt - day_start_dt).num_seconds();
+            chrono::Duration::seconds(duration.num_seconds() * seconds / span)
+        });
+        works.push(day_work);
+        day_start_dt = day_end_dt;
+    }
+    if works.is_empty() {
+        works.push(work);
+    }
+    works
+}
+
+/// Ends the work at the first midnight in the `tz` timezone after its start.
+///
+/// The end is exclusive, so the work stays within its start date, and the
+/// minutes of the work are not truncated as an end at 23:59:59 would be.
+fn clamp_to_day(work: &mut Work, tz: Tz) {
+    let Some(start_dt) = work.inner.start_datetime else {
+        return;
+    };
+    let date = start_dt.with_timezone(&tz).date_naive();
+    let midnight = config::midnight(date + chrono::Days::new(1), tz).to_utc();
+    if let Some(end_dt) = work.inner.end_datetime.as_mut() {
+        *end_dt = (*end_dt).min(midnight);
+    }
+}
+
+/// Reduces each group of overlapping works of the same date in the `tz`
+/// timezone to a single work. The merged work takes the task and the notes of
+/// the longest work of the group.
+fn dedup_overlaps(mut works: Vec<Work>, strategy: OverlapStrategy, tz: Tz) -> Vec<Work> {
+    let date = |work: &Work| {
+        work.inner
+            .start_datetime
+            .map(|dt| dt.with_timezone(&tz).date_naive())
+    };
+    works.sort_by_key(|work| (date(work), work.inner.start_datetime));
+    let mut groups: Vec<Vec<Work>> = vec![];
+    let mut group_end_dt = None;
+    for work in works {
+        let (start_dt, end_dt) = (work.inner.start_datetime, work.inner.end_datetime);
+        match groups.last_mut() {
+            Some(group)
+                if start_dt.is_some()
+                    && start_dt < group_end_dt
+                    && group.first().map(date) == Some(date(&work)) =>
+            {
+                group.push(work);
+                group_end_dt = group_end_dt.max(end_dt);
+            }
+            _ => {
+                groups.push(vec![work]);
+                group_end_dt = end_dt;
+            }
+        }
+    }
+    groups
+        .into_iter()
+        .filter_map(|group| {
+            let start_dt = group.first()?.inner.start_datetime;
+            let end_dt = group
+                .iter()
+                .filter_map(|work| work.inner.end_datetime)
+                .max();
+            let mut longest = group
+                .into_iter()
+                .rev()
+                .max_by_key(|work| work.inner.minutes().unwrap_or(0))?;
+            if let OverlapStrategy::Merge = strategy {
+                longest.inner.start_datetime = start_dt;
+                longest.inner.end_datetime = end_dt;
+                longest.inner.duration = None;
+            }
+            Some(longest)
+        })
+        .collect()
+}
+
+/// Joins the distinct `; `-separated fragments of the notes with `; `,
+/// in the first-seen order.
+fn join_unique_notes<'a>(notes: impl IntoIterator<Item = &'a str>) -> String {
+    let mut seen = HashSet::new();
+    notes
+        .into_iter()
+        .flat_map(|notes| notes.split("; "))
+        .filter(|fragment| !fragment.is_empty() && seen.insert(*fragment))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Merges the consecutive works of the same task on the same date in the `tz`
+/// timezone separated by at most `gap_minutes`. With `notes_match`, only the
+/// works whose notes both match are merged. The merged work keeps the notes
+/// of the first work, or the distinct notes of both with `unique_notes`, and
+/// logs the time of both works, without the gap.
+fn merge_works(
+    mut works: Vec<Work>,
+    gap_minutes: i64,
+    notes_match: Option<&Regex>,
+    unique_notes: bool,
+    tz: Tz,
+) -> Vec<Work> {
+    works.sort_by_key(|work| work.inner.start_datetime);
+    let gap = chrono::Duration::minutes(gap_minutes);
+    let matches = |work: &Work| match notes_match {
+        Some(regex) => work
+            .inner
+            .notes
+            .as_deref()
+            .is_some_and(|notes| regex.is_match(notes)),
+        None => true,
+    };
+    let mut merged: Vec<Work> = vec![];
+    for work in works {
+        if let Some(last) = merged.last_mut() {
+            let (l, r) = (&last.inner, &work.inner);
+            let same_task = l.task.name == r.task.name
+                && l.task.project == r.task.project
+                && l.task.project_code == r.task.project_code
+                && l.task.client == r.task.client;
+            let date = |dt: DateTime<Utc>| dt.with_timezone(&tz).date_naive();
+            let adjacent = match (l.start_datetime, l.end_datetime, r.start_datetime) {
+                (Some(l_start_dt), Some(end_dt), Some(start_dt)) => {
+                    start_dt >= end_dt
+                        && start_dt - end_dt <= gap
+                        && date(l_start_dt) == date(start_dt)
+                }
+                _ => false,
+            };
+            if same_task && adjacent && matches(last) && matches(&work) {
+                let minutes = l.minutes().unwrap_or(0) + r.minutes().unwrap_or(0);
+                last.inner.duration = Some(chrono::Duration::minutes(minutes));
+                last.inner.end_datetime = work.inner.end_datetime;
+                if unique_notes {
+                    let notes = [&last.inner.notes, &work.inner.notes]
+                        .into_iter()
+                        .filter_map(Option::as_deref);
+                    last.inner.notes = Some(join_unique_notes(notes));
+                }
+                continue;
+            }
+        }
+        merged.push(work);
+    }
+    merged
+}
+
+type DailyKey = (Option<NaiveDate>, String, String, String, String);
+
+/// Reduces the works of each date in the `tz` timezone and task to a single
+/// work, sorted by date. The work logs the total time, joins the distinct
+/// notes, and keeps the first non-empty value of each included property.
+fn aggregate_daily(mut works: Vec<Work>, tz: Tz) -> Vec<Work> {
+    works.sort_by_key(|work| work.inner.start_datetime);
+    let mut days: BTreeMap<DailyKey, Work> = BTreeMap::new();
+    for work in works {
+        let task = &work.inner.task;
+        let key = (
+            work.inner
+                .start_datetime
+                .map(|dt| dt.with_timezone(&tz).date_naive()),
+            task.client.clone(),
+            task.project.clone(),
+            task.project_code.clone(),
+            task.name.clone(),
+        );
+        let Some(day) = days.get_mut(&key) else {
+            days.insert(key, work);
+            continue;
+        };
+        let minutes = day.inner.minutes().unwrap_or(0) + work.inner.minutes().unwrap_or(0);
+        day.inner.duration = Some(chrono::Duration::minutes(minutes));
+        day.inner.end_datetime = day.inner.end_datetime.max(work.inner.end_datetime);
+        let notes = [&day.inner.notes, &work.inner.notes]
+            .into_iter()
+            .filter_map(Option::as_deref);
+        day.inner.notes = Some(join_unique_notes(notes));
+        for (day_prop, prop) in day.props.iter_mut().zip(work.props) {
+            if day_prop.as_deref().unwrap_or_default().is_empty() {
+                *day_prop = prop;
+            }
+        }
+    }
+    days.into_values().collect()
+}
+
+/// Scales the works of each day in the `tz` timezone so that the day totals
+/// exactly `hours`. The minutes lost to truncation go to the works with the
+/// largest remainders.
+fn normalize_days(works: &mut [Work], hours: f64, tz: Tz) {
+    let day_minutes = (hours * 60.0).round() as i64;
+    let mut days: HashMap<Option<NaiveDate>, Vec<usize>> = HashMap::new();
+    for (i, work) in works.iter().enumerate() {
+        let date = work
+            .inner
+            .start_datetime
+            .map(|dt| dt.with_timezone(&tz).date_naive());
+        days.entry(date).or_default().push(i);
+    }
+    for indices in days.into_values() {
+        let minutes = indices
+            .iter()
+            .map(|&i| works[i].inner.minutes().unwrap_or(0))
+            .collect::<Vec<_>>();
+        let total = minutes.iter().sum::<i64>();
+        if total <= 0 {
+            continue;
+        }
+        let mut scaled = minutes
+            .iter()
+            .map(|m| (m * day_minutes / total, m * day_minutes % total))
+            .collect::<Vec<_>>();
+        let mut rest = day_minutes - scaled.iter().map(|(m, _)| m).sum::<i64>();
+        let mut by_remainder = (0..scaled.len()).collect::<Vec<_>>();
+        by_remainder.sort_by_key(|&j| std::cmp::Reverse(scaled[j].1));
+        for j in by_remainder {
+            if rest <= 0 {
+                break;
+            }
+            scaled[j].0 += 1;
+            rest -= 1;
+        }
+        for (&i, (m, _)) in indices.iter().zip(scaled) {
+            works[i].inner.duration = Some(chrono::Duration::minutes(m));
+        }
+    }
+}
+
+fn sort_works(works: &mut [Work], config: &Config) {
+    let Some(sort) = &config.sort else {
+        return;
+    };
+    works.sort_by(|l, r| {
+        let (l, r) = (&l.inner, &r.inner);
+        let (date_l, date_r) = (
+            l.start_datetime
+                .map(|dt| dt.with_timezone(&config.timezone).date_naive()),
+            r.start_datetime
+                .map(|dt| dt.with_timezone(&config.timezone).date_naive()),
+        );
+        let date = if config.reverse {
+            date_r.cmp(&date_l)
+        } else {
+            date_l.cmp(&date_r)
+        };
+        let task = match sort {
+            SortOrder::Date => Ordering::Equal,
+            SortOrder::DateTask => l.task.name.cmp(&r.task.name),
+        };
+        let start = if config.reverse {
+            r.start_datetime.cmp(&l.start_datetime)
+        } else {
+            l.start_datetime.cmp(&r.start_datetime)
+        };
+        date.then(task).then(start)
+    });
+}
+
+/// The number of the warnings logged so far, printed or not.
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// The log target of the warning about the suppressed warnings, which
+/// --max-warnings neither counts nor suppresses.
+const SUPPRESSED_WARNINGS_TARGET: &str = "calvest::suppressed_warnings";
+
+/// Logs the warnings as `WARN: ...` and the information as is to stderr.
+fn init_logger(config: &Config) {
+    let level = match (config.quiet, config.verbosity) {
+        (true, _) => log::LevelFilter::Error,
+        (false, 0) => log::LevelFilter::Info,
+        (false, _) => log::LevelFilter::Debug,
+    };
+    let max_warnings = config.max_warnings;
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format(move |buf, record| match record.level() {
+            log::Level::Info => writeln!(buf, "{}", record.args()),
+            log::Level::Warn
+                if record.target() != SUPPRESSED_WARNINGS_TARGET
+                    && WARNINGS.fetch_add(1, AtomicOrdering::Relaxed)
+                        >= max_warnings.unwrap_or(usize::MAX) =>
+            {
+                Ok(())
+            }
+            level => writeln!(buf, "{level}: {}", record.args()),
+        })
+        .init();
+}
+
+/// Logs how many warnings --max-warnings suppressed, if any.
+fn report_suppressed_warnings(config: &Config) {
+    let Some(max_warnings) = config.max_warnings else {
+        return;
+    };
+    let suppressed = WARNINGS
+        .load(AtomicOrdering::Relaxed)
+        .saturating_sub(max_warnings);
+    if suppressed > 0 {
+        log::warn!(
+            target: SUPPRESSED_WARNINGS_TARGET,
+            "(suppressed {suppressed} further warnings)"
+        );
+    }
+}
+
+/// Reports the events that could not be processed and why.
+fn report_skipped(skipped: &[String]) {
+    if skipped.is_empty() {
+        return;
+    }
+    log::warn!("Skipped events: {}", skipped.len());
+    for reason in skipped {
+        log::warn!("  {reason}");
+    }
+}
+
 fn main() -> Result<()> {
     let config = config::config();
+    init_logger(&config);
+    let result = run(&config);
+    report_suppressed_warnings(&config);
+    result
+}
+
+fn run(config: &Config) -> Result<()> {
     //eprintln!("{config:?}");
 
-    let ical_reader = open_ical_reader(&config)?;
-    let mut csv_writer = open_csv_writer(&config)?;
+    let ical_readers = open_ical_readers(config)?;
+    let mut outputs = PendingOutputs::default();
+    let writes_output =
+        !(config.dry_run || config.summary_only || config.suggest_tasks || config.validate_harvest);
+    let mut writers = if writes_output {
+        open_writers(config, &mut outputs)?
+    } else {
+        vec![]
+    };
 
     let column_names = config
         .extra_props
         .iter()
         .map(String::as_str)
-        .chain(harvest::REQUIRED_CSV_COLUMN_NAMES.iter().cloned());
-    csv_writer
-        .write_record(column_names)
-        .map_err(|e| anyhow!("Cannot write the CSV headers to the output file: {e}"))?;
+        .chain(config.column_names())
+        .collect::<Vec<_>>();
+    for writer in writers.iter_mut() {
+        writer
+            .write_header(&column_names)
+            .map_err(|e| anyhow!("Cannot write the headers to the output file: {e}"))?;
+    }
 
-    announce_event_collection(&config);
+    announce_event_collection(config);
 
     let mut events = vec![];
-    for calendar in ical_reader {
-        let calendar = calendar?;
+    let mut duplicate_blocks = 0;
+    // The events that cannot be processed are reported together at the end.
+    let mut skipped = vec![];
+    let mut invalid_calendars = 0;
+    for calendar in ical_readers.into_iter().flat_map(|reader| {
+        // The rest of an input cannot be parsed reliably after an error.
+        let mut failed = false;
+        reader.take_while(move |calendar| !std::mem::replace(&mut failed, calendar.is_err()))
+    }) {
+        let calendar = match calendar {
+            Ok(calendar) => calendar,
+            Err(error) => {
+                skipped.push(format!("Invalid calendar: {error}"));
+                invalid_calendars += 1;
+                continue;
+            }
+        };
+        let timezones = TimeZones::from(&calendar.timezones).with_local(config.timezone);
+        let mut blocks = HashSet::new();
         for event in calendar.events {
-            let mut event_chain = relevant_events(&event, &config)?;
-            events.append(&mut event_chain);
+            if !blocks.insert(event_block_key(&event)) {
+                log::warn!(
+                    "Duplicate VEVENT block; the export may be corrupt. SUMMARY={:?} DTSTART={:?}",
+                    event.summary(),
+                    event.start_date()
+                );
+                duplicate_blocks += 1;
+            }
+            match relevant_events(&event, &timezones, config) {
+                Ok(mut event_chain) => events.append(&mut event_chain),
+                Err(error) => skipped.push(error.to_string()),
+            }
         }
     }
 
-    let mut deduplicated_events: HashMap<NaiveDate, HashMap<String, Event>> = HashMap::new();
-    for event in events {
-        if let Some(events) = deduplicated_events.get_mut(&event.start_dt.date_naive()) {
-            events.insert(event.uid.clone(), event);
-        } else {
-            let mut events = HashMap::new();
-            let start_dt = event.start_dt.clone();
-            events.insert(event.uid.clone(), event);
-            deduplicated_events.insert(start_dt.date_naive(), events);
+    // Recurring occurrences share the UID, so an event is a duplicate only
+    // when it also starts at the same time.
+    let mut seen_events = HashSet::new();
+    let mut duplicate_events = 0;
+    let mut events = events
+        .into_iter()
+        .filter(|event| {
+            let is_new = seen_events.insert((event.dedup_key(), event.start_dt));
+            if !is_new {
+                duplicate_events += 1;
+            }
+            is_new
+        })
+        .collect::<Vec<_>>();
+    if config.infer_end_from_next {
+        infer_end_from_next(&mut events, config);
+    }
+    let conflicts = if config.report_conflicts {
+        report_conflicts(&events)
+    } else {
+        0
+    };
+
+    let mut works = vec![];
+    for event in events.iter() {
+        match event_to_work(event, config) {
+            Ok(Some(work)) => works.push(work),
+            Ok(None) => {}
+            Err(error) => skipped.push(error.to_string()),
         }
     }
+    if let Some(strategy) = config.dedup_overlaps {
+        works = dedup_overlaps(works, strategy, config.timezone);
+    }
+    if let Some(gap) = config.merge_gap {
+        works = merge_works(
+            works,
+            gap,
+            config.merge_when_notes_match.as_ref(),
+            config.unique_notes,
+            config.timezone,
+        );
+    }
+    if config.clamp_to_day {
+        works
+            .iter_mut()
+            .for_each(|work| clamp_to_day(work, config.timezone));
+    }
+    if config.split_multiday {
+        works = works
+            .into_iter()
+            .flat_map(|work| split_multiday(work, config.timezone))
+            .collect();
+    }
+    if config.aggregate_daily {
+        works = aggregate_daily(works, config.timezone);
+    }
+    if let Some(hours) = config.normalize_day {
+        normalize_days(&mut works, hours, config.timezone);
+    }
+    sort_works(&mut works, config);
 
-    let mut work_entries = 0;
-    for event in deduplicated_events
-        .values()
-        .map(|events| events.values())
-        .flatten()
-    {
-        if let Some(work) = event_to_work(&event, &config)? {
-            log_work(&work, &mut csv_writer).map_err(|e| anyhow!("Cannot log work\n{e}"))?;
-            work_entries += 1;
+    let work_entries = works.len();
+    if let Some(max_rows) = config.max_rows {
+        if work_entries > max_rows {
+            report_skipped(&skipped);
+            return Err(anyhow!(
+                "{work_entries} work entries are more than {max_rows} (--max-rows)"
+            ));
         }
     }
+    for work in works.iter() {
+        log_work(work, config, &mut writers).map_err(|e| anyhow!("Cannot log work\n{e}"))?;
+    }
 
-    csv_writer
-        .flush()
-        .map_err(|e| anyhow!("Cannot write to the output file\n{e}"))?;
+    if config.dry_run {
+        print_preview(&works, &column_names, config)?;
+    }
+    let violations = if config.validate_harvest {
+        validate_harvest(&works, &column_names, config)?
+    } else {
+        0
+    };
+    if config.suggest_tasks {
+        print_task_suggestions(&works, config);
+    }
 
-    eprintln!();
-    eprintln!("Events collected. Work entries total: {work_entries}");
+    for writer in writers.iter_mut() {
+        writer
+            .flush()
+            .map_err(|e| anyhow!("Cannot write to the output file\n{e}"))?;
+    }
+    drop(writers);
+    if let (Some(dir), true) = (&config.split_by_month, writes_output) {
+        write_months(dir, &works, &column_names, config, &mut outputs)?;
+    }
+    // The events after a parse error are missing, so the previous output
+    // is better than the new one.
+    if invalid_calendars == 0 {
+        outputs.commit()?;
+    } else {
+        log::warn!("The outputs are left as they were: an input could not be parsed");
+    }
+
+    if config.summary_only || !config.quiet {
+        print_summary(&works, config);
+    }
+
+    log::info!("");
+    if duplicate_blocks > 0 {
+        log::warn!("Duplicate VEVENT blocks found: {duplicate_blocks}");
+    }
+    if duplicate_events > 0 {
+        log::info!("Duplicate events skipped: {duplicate_events}");
+    }
+    if conflicts > 0 {
+        log::warn!("Conflicting events found: {conflicts}");
+    }
+    log::info!("Events collected. Work entries total: {work_entries}");
+    report_skipped(&skipped);
+
+    if work_entries == 0 && config.fail_on_empty {
+        return Err(anyhow!("No work entries collected"));
+    }
+    if violations > 0 {
+        return Err(anyhow!(
+            "{violations} violations of the Harvest format rules"
+        ));
+    }
+    if !skipped.is_empty() {
+        return Err(anyhow!("{} events could not be processed", skipped.len()));
+    }
+
+    if let Some(command) = &config.post_hook {
+        run_post_hook(command, &works, config)?;
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A work of the `task` from `start` to `end`, RFC 3339 datetimes.
+    fn work(task: &str, start: &str, end: &str) -> Work {
+        let dt = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().to_utc();
+        let mut inner = harvest::Work::new(
+            "Ada".to_string(),
+            "Lovelace".to_string(),
+            harvest::Task {
+                name: task.to_string(),
+                project: "Engine".to_string(),
+                project_code: "EN".to_string(),
+                client: "Babbage".to_string(),
+            },
+        );
+        inner.start_datetime = Some(dt(start));
+        inner.end_datetime = Some(dt(end));
+        inner.notes = Some(task.to_string());
+        Work {
+            inner,
+            props: vec![],
+            location: None,
+            categories: vec![],
+            organizer: None,
+            uid: String::new(),
+            summary: None,
+            status: None,
+            task_matched: false,
+            rounding: None,
+            notes_template: None,
+        }
+    }
+
+    /// The start, the end, and the minutes of the works.
+    fn spans(works: &[Work]) -> Vec<(String, String, i64)> {
+        works
+            .iter()
+            .map(|work| {
+                let rfc3339 = |dt: Option<DateTime<Utc>>| dt.unwrap().to_rfc3339();
+                (
+                    rfc3339(work.inner.start_datetime),
+                    rfc3339(work.inner.end_datetime),
+                    work.inner.minutes().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn span(start: &str, end: &str, minutes: i64) -> (String, String, i64) {
+        (start.to_string(), end.to_string(), minutes)
+    }
+
+    /// The configuration of the `args`, with the names, the default task,
+    /// and the UTC timezone set.
+    fn config(args: &[&str]) -> Config {
+        let args = [
+            "calvest",
+            "--first-name",
+            "Ada",
+            "--last-name",
+            "Lovelace",
+            "--default-task",
+            "Development",
+            "Engine",
+            "EN",
+            "Babbage",
+            "--timezone",
+            "UTC",
+        ]
+        .iter()
+        .chain(args);
+        config::config_from(
+            config::cli().try_get_matches_from(args).unwrap(),
+            Utc::now(),
+        )
+    }
+
+    /// The occurrences of the VEVENT with the `properties`, CRLF-terminated
+    /// lines.
+    fn events(properties: &str, config: &Config) -> Vec<Event> {
+        let ics = format!(
+            "BEGIN:VCALENDAR\r\n\
+             BEGIN:VEVENT\r\n\
+             {properties}\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n"
+        );
+        let calendar = IcalParser::new(ics.as_bytes()).next().unwrap().unwrap();
+        let timezones = TimeZones::from(&calendar.timezones);
+        relevant_events(&calendar.events[0], &timezones, config).unwrap()
+    }
+
+    /// The works of the VEVENT with the `properties`, CRLF-terminated lines.
+    fn works(properties: &str, config: &Config) -> Vec<Work> {
+        events(properties, config)
+            .iter()
+            .filter_map(|event| event_to_work(event, config).unwrap())
+            .collect()
+    }
+
+    const MEETING: &str = "UID:sync@example.com\r\n\
+                           SUMMARY:Sync\r\n\
+                           DTSTART:20250106T090000Z\r\n\
+                           DTEND:20250106T093000Z\r\n";
+
+    #[test]
+    fn split_multiday_at_local_midnights() {
+        let works = split_multiday(
+            work(
+                "Deploy",
+                "2025-01-06T22:00:00+00:00",
+                "2025-01-08T02:00:00+00:00",
+            ),
+            Tz::UTC,
+        );
+        assert_eq!(
+            spans(&works),
+            [
+                span(
+                    "2025-01-06T22:00:00+00:00",
+                    "2025-01-07T00:00:00+00:00",
+                    120
+                ),
+                span(
+                    "2025-01-07T00:00:00+00:00",
+                    "2025-01-08T00:00:00+00:00",
+                    1440
+                ),
+                span(
+                    "2025-01-08T00:00:00+00:00",
+                    "2025-01-08T02:00:00+00:00",
+                    120
+                ),
+            ]
+        );
+        // Berlin midnight is 23:00 UTC in January.
+        let works = split_multiday(
+            work(
+                "Deploy",
+                "2025-01-06T22:00:00+00:00",
+                "2025-01-07T01:00:00+00:00",
+            ),
+            Tz::Europe__Berlin,
+        );
+        assert_eq!(
+            spans(&works),
+            [
+                span("2025-01-06T22:00:00+00:00", "2025-01-06T23:00:00+00:00", 60),
+                span(
+                    "2025-01-06T23:00:00+00:00",
+                    "2025-01-07T01:00:00+00:00",
+                    120
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_multiday_shares_an_overridden_duration() {
+        let mut all_day = work(
+            "Offsite",
+            "2025-01-06T00:00:00+00:00",
+            "2025-01-08T00:00:00+00:00",
+        );
+        all_day.inner.duration = Some(chrono::Duration::hours(16));
+        let works = split_multiday(all_day, Tz::UTC);
+        let minutes = works
+            .iter()
+            .map(|work| work.inner.minutes().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(minutes, [480, 480]);
+    }
+
+    #[test]
+    fn split_multiday_keeps_a_single_day() {
+        let works = split_multiday(
+            work(
+                "Review",
+                "2025-01-06T09:00:00+00:00",
+                "2025-01-06T10:00:00+00:00",
+            ),
+            Tz::UTC,
+        );
+        assert_eq!(
+            spans(&works),
+            [span(
+                "2025-01-06T09:00:00+00:00",
+                "2025-01-06T10:00:00+00:00",
+                60
+            )]
+        );
+    }
+
+    #[test]
+    fn clamp_to_day_crossing_midnight() {
+        let mut late = work(
+            "Call",
+            "2025-01-06T23:30:00+00:00",
+            "2025-01-07T00:30:00+00:00",
+        );
+        clamp_to_day(&mut late, Tz::UTC);
+        assert_eq!(
+            spans(&[late]),
+            [span(
+                "2025-01-06T23:30:00+00:00",
+                "2025-01-07T00:00:00+00:00",
+                30
+            )]
+        );
+        // Berlin midnight is 23:00 UTC in January.
+        let mut late = work(
+            "Call",
+            "2025-01-06T22:30:00+00:00",
+            "2025-01-06T23:30:00+00:00",
+        );
+        clamp_to_day(&mut late, Tz::Europe__Berlin);
+        assert_eq!(
+            spans(&[late]),
+            [span(
+                "2025-01-06T22:30:00+00:00",
+                "2025-01-06T23:00:00+00:00",
+                30
+            )]
+        );
+    }
+
+    #[test]
+    fn clamp_to_day_within_a_day() {
+        let mut call = work(
+            "Call",
+            "2025-01-06T22:30:00+00:00",
+            "2025-01-06T23:30:00+00:00",
+        );
+        clamp_to_day(&mut call, Tz::UTC);
+        assert_eq!(
+            spans(&[call]),
+            [span(
+                "2025-01-06T22:30:00+00:00",
+                "2025-01-06T23:30:00+00:00",
+                60
+            )]
+        );
+    }
+
+    #[test]
+    fn merge_works_within_the_gap() {
+        let works = vec![
+            work(
+                "Review",
+                "2025-01-06T09:00:00+00:00",
+                "2025-01-06T09:30:00+00:00",
+            ),
+            work(
+                "Review",
+                "2025-01-06T09:40:00+00:00",
+                "2025-01-06T10:00:00+00:00",
+            ),
+            work(
+                "Review",
+                "2025-01-06T10:30:00+00:00",
+                "2025-01-06T11:00:00+00:00",
+            ),
+            work(
+                "Design",
+                "2025-01-06T11:00:00+00:00",
+                "2025-01-06T12:00:00+00:00",
+            ),
+        ];
+        let merged = merge_works(works, 10, None, false, Tz::UTC);
+        // The gap is not logged.
+        assert_eq!(
+            spans(&merged),
+            [
+                span("2025-01-06T09:00:00+00:00", "2025-01-06T10:00:00+00:00", 50),
+                span("2025-01-06T10:30:00+00:00", "2025-01-06T11:00:00+00:00", 30),
+                span("2025-01-06T11:00:00+00:00", "2025-01-06T12:00:00+00:00", 60),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_works_within_a_date() {
+        let works = vec![
+            work(
+                "Deploy",
+                "2025-01-06T23:00:00+00:00",
+                "2025-01-07T00:00:00+00:00",
+            ),
+            work(
+                "Deploy",
+                "2025-01-07T00:00:00+00:00",
+                "2025-01-07T01:00:00+00:00",
+            ),
+        ];
+        assert_eq!(merge_works(works.clone(), 0, None, false, Tz::UTC).len(), 2);
+        // Both start on 2025-01-07 in Tokyo.
+        assert_eq!(merge_works(works, 0, None, false, Tz::Asia__Tokyo).len(), 1);
+    }
+
+    #[test]
+    fn merge_works_with_matching_notes() {
+        let mut first = work(
+            "Review",
+            "2025-01-06T09:00:00+00:00",
+            "2025-01-06T09:30:00+00:00",
+        );
+        first.inner.notes = Some("PR 1".to_string());
+        let mut second = work(
+            "Review",
+            "2025-01-06T09:30:00+00:00",
+            "2025-01-06T10:00:00+00:00",
+        );
+        second.inner.notes = Some("PR 2".to_string());
+        let mut third = work(
+            "Review",
+            "2025-01-06T10:00:00+00:00",
+            "2025-01-06T10:30:00+00:00",
+        );
+        third.inner.notes = Some("Lunch".to_string());
+        let regex = Regex::new("^PR").unwrap();
+        let works = vec![first, second, third];
+
+        let merged = merge_works(works.clone(), 0, Some(&regex), false, Tz::UTC);
+        let notes = merged
+            .iter()
+            .map(|work| work.inner.notes.clone().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(notes, ["PR 1", "Lunch"]);
+
+        let merged = merge_works(works, 0, None, true, Tz::UTC);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].inner.notes.as_deref(), Some("PR 1; PR 2; Lunch"));
+    }
+
+    #[test]
+    fn join_unique_notes_keeps_the_first_seen_order() {
+        assert_eq!(
+            join_unique_notes(["Design", "Review; Design", "", "Sync; Review"]),
+            "Design; Review; Sync"
+        );
+        assert_eq!(join_unique_notes(["Sync", "Sync"]), "Sync");
+    }
+
+    #[test]
+    fn aggregate_daily_sums_a_day_into_one_work() {
+        let mut works = vec![
+            work("Design", "2025-01-06T13:00:00Z", "2025-01-06T13:30:00Z"),
+            work("Design", "2025-01-06T09:00:00Z", "2025-01-06T10:00:00Z"),
+            work("Design", "2025-01-06T15:00:00Z", "2025-01-06T15:15:00Z"),
+            work("Design", "2025-01-07T09:00:00Z", "2025-01-07T10:00:00Z"),
+        ];
+        works[0].inner.notes = Some("Wireframes".to_string());
+        works[2].inner.notes = Some("Wireframes".to_string());
+        let works = aggregate_daily(works, Tz::UTC);
+        assert_eq!(
+            spans(&works),
+            [
+                span(
+                    "2025-01-06T09:00:00+00:00",
+                    "2025-01-06T15:15:00+00:00",
+                    105
+                ),
+                span("2025-01-07T09:00:00+00:00", "2025-01-07T10:00:00+00:00", 60),
+            ]
+        );
+        assert_eq!(works[0].inner.notes.as_deref(), Some("Design; Wireframes"));
+    }
+
+    #[test]
+    fn normalize_days_scales_a_day_to_the_hours() {
+        let mut works = vec![
+            work("Design", "2025-01-06T09:00:00Z", "2025-01-06T10:00:00Z"),
+            work("Review", "2025-01-06T13:00:00Z", "2025-01-06T13:30:00Z"),
+            work("Sync", "2025-01-07T09:00:00Z", "2025-01-07T09:00:00Z"),
+        ];
+        normalize_days(&mut works, 8.0, Tz::UTC);
+        let minutes = spans(&works)
+            .into_iter()
+            .map(|(_, _, minutes)| minutes)
+            .collect::<Vec<_>>();
+        // 2:1 as logged, summing to 8 hours; the empty day is left as-is.
+        assert_eq!(minutes, [320, 160, 0]);
+        assert_eq!(minutes[0] + minutes[1], 8 * 60);
+    }
+
+    #[test]
+    fn dedup_overlaps_keeps_the_longest() {
+        let works = vec![
+            work(
+                "Tentative",
+                "2025-01-06T09:00:00+00:00",
+                "2025-01-06T09:30:00+00:00",
+            ),
+            work(
+                "Meeting",
+                "2025-01-06T09:15:00+00:00",
+                "2025-01-06T10:15:00+00:00",
+            ),
+            work(
+                "Review",
+                "2025-01-06T11:00:00+00:00",
+                "2025-01-06T11:30:00+00:00",
+            ),
+        ];
+        let kept = dedup_overlaps(works.clone(), OverlapStrategy::KeepLongest, Tz::UTC);
+        assert_eq!(
+            spans(&kept),
+            [
+                span("2025-01-06T09:15:00+00:00", "2025-01-06T10:15:00+00:00", 60),
+                span("2025-01-06T11:00:00+00:00", "2025-01-06T11:30:00+00:00", 30),
+            ]
+        );
+        let merged = dedup_overlaps(works, OverlapStrategy::Merge, Tz::UTC);
+        assert_eq!(merged[0].inner.task.name, "Meeting");
+        assert_eq!(
+            spans(&merged),
+            [
+                span("2025-01-06T09:00:00+00:00", "2025-01-06T10:15:00+00:00", 75),
+                span("2025-01-06T11:00:00+00:00", "2025-01-06T11:30:00+00:00", 30),
+            ]
+        );
+    }
+
+    #[test]
+    fn dedup_overlaps_within_a_date() {
+        let works = vec![
+            work(
+                "Deploy",
+                "2025-01-06T22:00:00+00:00",
+                "2025-01-07T01:00:00+00:00",
+            ),
+            work(
+                "Hotfix",
+                "2025-01-07T00:30:00+00:00",
+                "2025-01-07T00:45:00+00:00",
+            ),
+            work(
+                "Standup",
+                "2025-01-07T00:40:00+00:00",
+                "2025-01-07T02:00:00+00:00",
+            ),
+        ];
+        // The Hotfix and the Standup start on the next date: they overlap
+        // each other, but not the Deploy of the previous date.
+        let kept = dedup_overlaps(works.clone(), OverlapStrategy::KeepLongest, Tz::UTC);
+        let tasks = kept
+            .iter()
+            .map(|work| work.inner.task.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(tasks, ["Deploy", "Standup"]);
+        // All three start on 2025-01-06 in New York.
+        let kept = dedup_overlaps(works, OverlapStrategy::KeepLongest, Tz::America__New_York);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].inner.task.name, "Deploy");
+    }
+
+    #[test]
+    fn declined_by_me_is_excluded() {
+        let config = config(&["--my-email", "ada@example.com"]);
+        let declined = format!(
+            "{MEETING}ATTENDEE;PARTSTAT=DECLINED:mailto:Ada@example.com\r\n\
+             ATTENDEE;PARTSTAT=ACCEPTED:mailto:charles@example.com\r\n"
+        );
+        assert!(works(&declined, &config).is_empty());
+        let accepted = format!(
+            "{MEETING}ATTENDEE;PARTSTAT=ACCEPTED:mailto:ada@example.com\r\n\
+             ATTENDEE;PARTSTAT=DECLINED:mailto:charles@example.com\r\n"
+        );
+        assert_eq!(works(&accepted, &config).len(), 1);
+    }
+
+    #[test]
+    fn cancelled_is_excluded() {
+        let config = config(&[]);
+        let cancelled = format!("{MEETING}STATUS:CANCELLED\r\n");
+        assert!(works(&cancelled, &config).is_empty());
+        let confirmed = format!("{MEETING}STATUS:CONFIRMED\r\n");
+        assert_eq!(works(&confirmed, &config).len(), 1);
+    }
+
+    /// The Notes column of the work, without included properties.
+    fn notes(work: &Work, config: &Config) -> String {
+        work_record(work, config).unwrap()[5].clone()
+    }
+
+    /// The value of the column `name` of the work, without included properties.
+    fn column(work: &Work, config: &Config, name: &str) -> String {
+        let i = config.column_names().iter().position(|c| *c == name);
+        work_record(work, config).unwrap()[i.unwrap()].clone()
+    }
+
+    #[test]
+    fn excluded_summary_yields_no_work() {
+        let config = config(&["--exclude-pattern", "^Lunch$", "--exclude-pattern", "Focus"]);
+        let lunch = MEETING.replace("SUMMARY:Sync", "SUMMARY:Lunch");
+        assert!(works(&lunch, &config).is_empty());
+        let focus = MEETING.replace("SUMMARY:Sync", "SUMMARY:Focus time");
+        assert!(works(&focus, &config).is_empty());
+        let late_lunch = MEETING.replace("SUMMARY:Sync", "SUMMARY:Lunch and learn");
+        assert_eq!(works(&late_lunch, &config).len(), 1);
+        assert_eq!(works(MEETING, &config).len(), 1);
+    }
+
+    #[test]
+    fn ignore_case_matches_the_task_regardless_of_case() {
+        let task = ["--task", "Meetings", "Engine", "EN", "Babbage", "standup"];
+        let standup = MEETING.replace("SUMMARY:Sync", "SUMMARY:STANDUP");
+        let task_name = |config: &Config| works(&standup, config)[0].inner.task.name.clone();
+        assert_eq!(task_name(&config(&task)), "Development");
+        let ignore_case = [&task[..], &["--ignore-case"]].concat();
+        assert_eq!(task_name(&config(&ignore_case)), "Meetings");
+    }
+
+    #[test]
+    fn min_duration_drops_the_shorter_events() {
+        let config = config(&["--min-duration", "10"]);
+        let reminder = MEETING.replace("DTEND:20250106T093000Z", "DTEND:20250106T090500Z");
+        assert!(works(&reminder, &config).is_empty());
+        let short = MEETING.replace("DTEND:20250106T093000Z", "DTEND:20250106T091500Z");
+        assert_eq!(works(&short, &config).len(), 1);
+    }
+
+    #[test]
+    fn matched_task_logs_the_fixed_hours() {
+        let minutes = |properties: &str, config: &Config| {
+            works(properties, config)[0].inner.minutes().unwrap()
+        };
+        let task_hours = config(&["--task-hours", "^Sync$", "1.5"]);
+        assert_eq!(minutes(MEETING, &task_hours), 90);
+        let sync_up = MEETING.replace("SUMMARY:Sync", "SUMMARY:Sync up");
+        assert_eq!(minutes(&sync_up, &task_hours), 30);
+        let config_file = config(&[
+            "--config",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/config.toml"),
+        ]);
+        let review = MEETING
+            .replace("SUMMARY:Sync", "SUMMARY:Code review")
+            .replace("DTEND:20250106T093000Z", "DTEND:20250106T110000Z");
+        assert_eq!(works(&review, &config_file)[0].inner.task.name, "Review");
+        assert_eq!(minutes(&review, &config_file), 30);
+    }
+
+    #[test]
+    fn task_notes_template_overrides_the_global_one() {
+        let config = config(&["--notes-template", "{client}: {notes}"]);
+        let mut work = works(MEETING, &config).remove(0);
+        assert_eq!(notes(&work, &config), "Babbage: Sync");
+        work.notes_template = Some("[{task}/{project}] {summary}".to_string());
+        assert_eq!(notes(&work, &config), "[Development/Engine] Sync");
+    }
+
+    #[test]
+    fn combined_name_follows_the_format() {
+        assert_eq!(
+            combined_name("{last}, {first}", "Ada", "Lovelace"),
+            "Lovelace, Ada"
+        );
+        assert_eq!(
+            combined_name("{first} {last}", "Ada", "Lovelace"),
+            "Ada Lovelace"
+        );
+    }
+
+    #[test]
+    fn location_keeps_only_the_matching_events() {
+        let config = config(&["--location", "(?i)acme hq"]);
+        let on_site = format!("{MEETING}LOCATION:ACME HQ\\, Room 4\r\n");
+        assert_eq!(works(&on_site, &config).len(), 1);
+        let remote = format!("{MEETING}LOCATION:Zoom\r\n");
+        assert!(works(&remote, &config).is_empty());
+        assert!(works(MEETING, &config).is_empty());
+    }
+
+    #[test]
+    fn all_day_event_logs_the_all_day_hours() {
+        let config = config(&["--all-day-hours", "6"]);
+        let holiday = "UID:offsite@example.com\r\n\
+                       SUMMARY:Offsite\r\n\
+                       DTSTART;VALUE=DATE:20250106\r\n\
+                       DTEND;VALUE=DATE:20250107\r\n";
+        let works = works(holiday, &config);
+        assert_eq!(works.len(), 1);
+        assert_eq!(works[0].inner.minutes(), Some(6 * 60));
+        assert_eq!(work_record(&works[0], &config).unwrap()[6], "6.00");
+    }
+
+    #[test]
+    fn organizer_column_is_normalized() {
+        let config = config(&["--with-organizer"]);
+        let organizer =
+            format!("{MEETING}ORGANIZER;CN=\"Charles Babbage\":MAILTO:Charles@Example.com\r\n");
+        let work = &works(&organizer, &config)[0];
+        assert_eq!(
+            column(work, &config, "Organizer"),
+            "Charles Babbage <charles@example.com>"
+        );
+        let organizer = format!("{MEETING}ORGANIZER:mailto:charles@example.com\r\n");
+        let work = &works(&organizer, &config)[0];
+        assert_eq!(column(work, &config, "Organizer"), "charles@example.com");
+        assert_eq!(
+            column(&works(MEETING, &config)[0], &config, "Organizer"),
+            ""
+        );
+    }
+
+    #[test]
+    fn full_match_anchor_matches_the_whole_summary() {
+        let task = ["--task", "Meetings", "Engine", "EN", "Babbage", "meeting"];
+        let task_name = |summary: &str, config: &Config| {
+            let event = MEETING.replace("SUMMARY:Sync", &format!("SUMMARY:{summary}"));
+            works(&event, config)[0].inner.task.name.clone()
+        };
+        let substring = config(&task);
+        assert_eq!(task_name("team meeting", &substring), "Meetings");
+        let full = config(&[&task[..], &["--match-anchor", "full"]].concat());
+        assert_eq!(task_name("team meeting", &full), "Development");
+        assert_eq!(task_name("meeting", &full), "Meetings");
+    }
+
+    #[test]
+    fn only_past_excludes_an_event_tomorrow() {
+        let meeting = |day: DateTime<Utc>| {
+            let dt = |dt: DateTime<Utc>| dt.format("%Y%m%dT%H%M%SZ").to_string();
+            format!(
+                "UID:sync@example.com\r\n\
+                 SUMMARY:Sync\r\n\
+                 DTSTART:{}\r\n\
+                 DTEND:{}\r\n",
+                dt(day),
+                dt(day + chrono::Duration::minutes(30))
+            )
+        };
+        let now = Utc::now();
+        let yesterday = meeting(now - chrono::Duration::days(1));
+        let tomorrow = meeting(now + chrono::Duration::days(1));
+        let all = config(&["--end-date", "2100-01-01"]);
+        assert_eq!(works(&tomorrow, &all).len(), 1);
+        let only_past = config(&["--end-date", "2100-01-01", "--only-past"]);
+        assert_eq!(works(&yesterday, &only_past).len(), 1);
+        assert!(works(&tomorrow, &only_past).is_empty());
+    }
+
+    #[test]
+    fn date_column_follows_the_date_format() {
+        let date = |format: &str| {
+            let config = config(&["--date-format", format]);
+            column(&works(MEETING, &config)[0], &config, "Date")
+        };
+        assert_eq!(date("%Y-%m-%d"), "2025-01-06");
+        assert_eq!(date("%-m/%-d/%Y"), "1/6/2025");
+    }
+
+    #[test]
+    fn all_day_and_end_less_events_use_their_own_hours() {
+        let config = config(&["--all-day-hours", "6", "--default-hours", "0.5"]);
+        let all_day = "UID:offsite@example.com\r\n\
+                       SUMMARY:Offsite\r\n\
+                       DTSTART;VALUE=DATE:20250106\r\n";
+        assert_eq!(works(all_day, &config)[0].inner.minutes(), Some(6 * 60));
+        let end_less = "UID:call@example.com\r\n\
+                        SUMMARY:Call\r\n\
+                        DTSTART:20250106T090000Z\r\n";
+        assert_eq!(works(end_less, &config)[0].inner.minutes(), Some(30));
+    }
+
+    #[test]
+    fn tentative_attendance_needs_include_tentative() {
+        let tentative = format!(
+            "{MEETING}ATTENDEE;PARTSTAT=TENTATIVE:mailto:ada@example.com\r\n\
+             ATTENDEE;PARTSTAT=ACCEPTED:mailto:charles@example.com\r\n"
+        );
+        let required = ["--required-attendee", "ada@example.com"];
+        assert!(works(&tentative, &config(&required)).is_empty());
+        let config = config(&[&required[..], &["--include-tentative"]].concat());
+        assert_eq!(works(&tentative, &config).len(), 1);
+    }
+
+    #[test]
+    fn summary_less_event_falls_back_to_the_description() {
+        let config = config(&[
+            "--notes-from",
+            "summary,description",
+            "--task",
+            "Review",
+            "Engine",
+            "EN",
+            "Babbage",
+            "review",
+        ]);
+        let summary_less = "UID:review@example.com\r\n\
+                            DESCRIPTION:Code review of the mill\r\n\
+                            DTSTART:20250106T090000Z\r\n\
+                            DTEND:20250106T093000Z\r\n";
+        let work = &works(summary_less, &config)[0];
+        assert_eq!(notes(work, &config), "Code review of the mill");
+        assert_eq!(work.inner.task.name, "Review");
+        let blank_summary = format!("SUMMARY: \r\n{summary_less}");
+        let work = &works(&blank_summary, &config)[0];
+        assert_eq!(notes(work, &config), "Code review of the mill");
+        assert_eq!(work.inner.task.name, "Review");
+    }
+
+    #[test]
+    fn occurrences_have_distinct_uids_with_the_master_prefix() {
+        let config = config(&["--end-date", "2025-01-31"]);
+        let daily = format!("{MEETING}RRULE:FREQ=DAILY;COUNT=3\r\n");
+        let uids = events(&daily, &config)
+            .iter()
+            .map(occurrence_uid)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            uids,
+            [
+                "sync@example.com_20250106T090000Z",
+                "sync@example.com_20250107T090000Z",
+                "sync@example.com_20250108T090000Z",
+            ]
+        );
+        assert_eq!(
+            occurrence_uid(&events(MEETING, &config)[0]),
+            "sync@example.com"
+        );
+    }
+
+    #[test]
+    fn strip_notes_regex_removes_the_ticket_id() {
+        let ticket = MEETING.replace("SUMMARY:Sync", "SUMMARY:[ENG-1234] Sync on the mill");
+        let strip = ["--strip-notes-regex", r"\[[A-Z]+-\d+\]"];
+        let removed = config(&strip);
+        assert_eq!(
+            notes(&works(&ticket, &removed)[0], &removed),
+            "Sync on the mill"
+        );
+        let replaced = config(&[&strip[..], &["--strip-notes-replacement", "[redacted]"]].concat());
+        assert_eq!(
+            notes(&works(&ticket, &replaced)[0], &replaced),
+            "[redacted] Sync on the mill"
+        );
+    }
+
+    #[test]
+    fn skip_decorative_drops_an_emoji_only_summary() {
+        let emoji = MEETING.replace("SUMMARY:Sync", "SUMMARY:🎉 🎉");
+        let separator = MEETING.replace("SUMMARY:Sync", "SUMMARY:-----");
+        let with_emoji = MEETING.replace("SUMMARY:Sync", "SUMMARY:🎉 Launch");
+        assert_eq!(works(&emoji, &config(&[])).len(), 1);
+        let config = config(&["--skip-decorative"]);
+        assert!(works(&emoji, &config).is_empty());
+        assert!(works(&separator, &config).is_empty());
+        assert_eq!(works(&with_emoji, &config).len(), 1);
+    }
+
+    #[test]
+    fn task_by_category_with_the_default_fallback() {
+        let config = config(&[
+            "--task-by-category",
+            "Client Work",
+            "Consulting",
+            "Engine",
+            "EN",
+            "Babbage",
+            "--task",
+            "Meetings",
+            "Engine",
+            "EN",
+            "Babbage",
+            "Sync",
+        ]);
+        let task_name = |properties: &str| works(properties, &config)[0].inner.task.name.clone();
+        let client_work = format!("{MEETING}CATEGORIES:Internal,Client Work\r\n");
+        assert_eq!(task_name(&client_work), "Consulting");
+        let internal = format!("{MEETING}CATEGORIES:Internal\r\n");
+        assert_eq!(task_name(&internal), "Meetings");
+        let other = MEETING.replace("SUMMARY:Sync", "SUMMARY:Lunch");
+        assert_eq!(task_name(&other), "Development");
+    }
+
+    #[test]
+    fn overlapping_occurrences_of_two_series_conflict() {
+        let config = config(&["--end-date", "2025-01-31"]);
+        let standup = format!("{MEETING}RRULE:FREQ=DAILY;COUNT=3\r\n");
+        let review = "UID:review@example.com\r\n\
+                      SUMMARY:Review\r\n\
+                      DTSTART:20250106T091500Z\r\n\
+                      DTEND:20250106T100000Z\r\n\
+                      RRULE:FREQ=DAILY;INTERVAL=2;COUNT=2\r\n";
+        let lunch = "UID:lunch@example.com\r\n\
+                     SUMMARY:Lunch\r\n\
+                     DTSTART:20250106T100000Z\r\n\
+                     DTEND:20250106T103000Z\r\n";
+        let all = [
+            events(&standup, &config),
+            events(review, &config),
+            events(lunch, &config),
+        ]
+        .concat();
+        // The review overlaps the standup on 2025-01-06 and 2025-01-08;
+        // the lunch starts as the review ends.
+        assert_eq!(report_conflicts(&all), 2);
+        assert_eq!(report_conflicts(&events(&standup, &config)), 0);
+    }
+
+    #[test]
+    fn minutes_column_beside_the_hours() {
+        let config = config(&["--with-minutes"]);
+        let long = MEETING.replace("DTEND:20250106T093000Z", "DTEND:20250106T103000Z");
+        let work = &works(&long, &config)[0];
+        assert_eq!(column(work, &config, "Minutes"), "90");
+        assert_eq!(column(work, &config, "Hours"), "1.50");
+    }
+
+    #[test]
+    fn summary_column_keeps_the_original_text() {
+        let config = config(&["--with-summary", "--strip-notes-regex", r"\[[A-Z]+-\d+\]"]);
+        let ticket = MEETING.replace("SUMMARY:Sync", "SUMMARY:[ENG-1234] Sync\\, mill");
+        let work = &works(&ticket, &config)[0];
+        assert_eq!(column(work, &config, "Summary"), "[ENG-1234] Sync, mill");
+        assert_eq!(column(work, &config, "Notes"), "Sync, mill");
+    }
+
+    #[test]
+    fn validate_harvest_flags_an_empty_task() {
+        let config = config(&["--validate-harvest"]);
+        let mut works = works(MEETING, &config);
+        let column_names = config.column_names();
+        assert_eq!(validate_harvest(&works, &column_names, &config).unwrap(), 0);
+        works[0].inner.task.name = " ".to_string();
+        assert_eq!(validate_harvest(&works, &column_names, &config).unwrap(), 1);
+        let record = work_record(&works[0], &config).unwrap();
+        let record = record.iter().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(
+            harvest::validate_record(&column_names, &record),
+            ["Task is empty"]
+        );
+    }
+
+    #[test]
+    fn status_column_shows_the_event_status() {
+        let config = config(&["--with-status"]);
+        let tentative = format!("{MEETING}STATUS:TENTATIVE\r\n");
+        let work = &works(&tentative, &config)[0];
+        assert_eq!(column(work, &config, "Status"), "TENTATIVE");
+        assert_eq!(column(&works(MEETING, &config)[0], &config, "Status"), "");
+    }
+
+    #[test]
+    fn drop_notes_after_the_first_matching_line() {
+        let regex = Regex::new("^-- ").unwrap();
+        assert_eq!(
+            drop_notes_after("Agenda\nDemo\n-- \nAda\n-- \nmore", &regex),
+            "Agenda\nDemo"
+        );
+        assert_eq!(drop_notes_after("Agenda", &regex), "Agenda");
+        // The escaped newlines of the TEXT value split the lines, too.
+        let config = config(&[
+            "--notes-from",
+            "description",
+            "--drop-notes-after",
+            "^Join Zoom",
+        ]);
+        let event = format!(
+            "{MEETING}DESCRIPTION:Sprint review\\nDemo\\nJoin Zoom Meeting\\nhttps://zoom.us/j/1\r\n"
+        );
+        let works = works(&event, &config);
+        assert_eq!(notes(&works[0], &config), "Sprint review\nDemo");
+    }
+
+    #[test]
+    fn infer_end_from_next_chains_the_events() {
+        let config = config(&[
+            "--infer-end-from-next",
+            "--max-inferred-hours",
+            "2",
+            "--default-hours",
+            "0.5",
+        ]);
+        let open_ended = |uid: &str, start: &str| {
+            events(
+                &format!("UID:{uid}\r\nSUMMARY:Block\r\nDTSTART:{start}\r\n"),
+                &config,
+            )
+        };
+        let mut events = [
+            open_ended("third", "20250106T130000Z"),
+            open_ended("first", "20250106T090000Z"),
+            open_ended("second", "20250106T100000Z"),
+        ]
+        .concat();
+        infer_end_from_next(&mut events, &config);
+        let spans = events
+            .iter()
+            .map(|event| {
+                (
+                    event.uid.as_str(),
+                    (event.end_dt - event.start_dt).num_minutes(),
+                )
+            })
+            .collect::<Vec<_>>();
+        // Up to the next start, capped at 2 hours, and the default hours last.
+        assert_eq!(spans, [("first", 60), ("second", 120), ("third", 30)]);
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::Result;
+use serde_json::Map;
+use serde_json::Value;
+use std::io::Write;
+
+/// A destination for the work records.
+pub(crate) trait RecordWriter {
+    fn write_header(&mut self, columns: &[&str]) -> Result<()>;
+    fn write_record(&mut self, record: &[&str]) -> Result<()>;
+    fn flush(&mut self) -> Result<()>;
+}
+
+impl<W: Write> RecordWriter for csv::Writer<W> {
+    fn write_header(&mut self, columns: &[&str]) -> Result<()> {
+        Ok(csv::Writer::write_record(self, columns)?)
+    }
+
+    fn write_record(&mut self, record: &[&str]) -> Result<()> {
+        Ok(csv::Writer::write_record(self, record)?)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(csv::Writer::flush(self)?)
+    }
+}
+
+fn json_object(columns: &[String], record: &[&str]) -> Value {
+    Value::Object(
+        columns
+            .iter()
+            .zip(record)
+            .map(|(c, v)| (c.clone(), Value::String(v.to_string())))
+            .collect::<Map<_, _>>(),
+    )
+}
+
+/// Writes all the records as a single JSON array of objects on flush.
+pub(crate) struct JsonWriter<W: Write> {
+    writer: W,
+    columns: Vec<String>,
+    records: Vec<Value>,
+}
+
+impl<W: Write> JsonWriter<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self {
+            writer,
+            columns: vec![],
+            records: vec![],
+        }
+    }
+}
+
+impl<W: Write> RecordWriter for JsonWriter<W> {
+    fn write_header(&mut self, columns: &[&str]) -> Result<()> {
+        self.columns = columns.iter().map(|c| c.to_string()).collect();
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &[&str]) -> Result<()> {
+        self.records.push(json_object(&self.columns, record));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        serde_json::to_writer_pretty(&mut self.writer, &self.records)?;
+        writeln!(self.writer)?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Writes every record as a JSON object on its own line.
+pub(crate) struct JsonLinesWriter<W: Write> {
+    writer: W,
+    columns: Vec<String>,
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self {
+            writer,
+            columns: vec![],
+        }
+    }
+}
+
+impl<W: Write> RecordWriter for JsonLinesWriter<W> {
+    fn write_header(&mut self, columns: &[&str]) -> Result<()> {
+        self.columns = columns.iter().map(|c| c.to_string()).collect();
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &[&str]) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, &json_object(&self.columns, record))?;
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
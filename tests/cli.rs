@@ -0,0 +1,786 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Output;
+
+const NAMES: &[&str] = &["--first-name", "Ada", "--last-name", "Lovelace"];
+const DEFAULT_TASK: &[&str] = &["--default-task", "Development", "Engine", "EN", "Babbage"];
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// An empty directory for the outputs of the test `name`.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("calvest-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn calvest(args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_calvest"));
+    command
+        .args(NAMES)
+        .args(DEFAULT_TASK)
+        .args(args)
+        .env("CALVEST_TIMEZONE", "UTC")
+        .env_remove("CALVEST_FIRST_NAME")
+        .env_remove("CALVEST_LAST_NAME")
+        .env_remove("CALVEST_DEFAULT_TASK");
+    command
+}
+
+fn run(command: &mut Command) -> Output {
+    let output = command.output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+/// The data rows of the CSV.
+fn rows(csv: &str) -> Vec<&str> {
+    csv.lines().skip(1).collect()
+}
+
+#[test]
+fn multiple_inputs_are_merged_and_deduplicated() {
+    let dir = temp_dir("multiple-inputs");
+    let output = dir.join("out.csv");
+    run(&mut calvest(&[
+        "--input",
+        fixture("client_a.ics").to_str().unwrap(),
+        "--input",
+        fixture("client_b.ics").to_str().unwrap(),
+        "--output",
+        output.to_str().unwrap(),
+    ]));
+    let csv = fs::read_to_string(&output).unwrap();
+    let rows = rows(&csv);
+    // The Sync meeting is in both calendars.
+    assert_eq!(rows.len(), 3, "{csv}");
+    assert_eq!(rows.iter().filter(|row| row.contains(",Sync,")).count(), 1);
+}
+
+#[test]
+fn parse_error_leaves_the_output_intact() {
+    let dir = temp_dir("parse-error");
+    let output = dir.join("out.csv");
+    fs::write(&output, "previous\n").unwrap();
+    let result = calvest(&[
+        "--input",
+        fixture("unparsable.ics").to_str().unwrap(),
+        "--output",
+        output.to_str().unwrap(),
+    ])
+    .output()
+    .unwrap();
+    assert!(!result.status.success());
+    assert_eq!(fs::read_to_string(&output).unwrap(), "previous\n");
+    assert!(!dir.join("out.csv.tmp").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn output_through_a_symlink_replaces_the_target() {
+    let dir = temp_dir("symlink");
+    let target = dir.join("target.csv");
+    let link = dir.join("link.csv");
+    fs::write(&target, "previous\n").unwrap();
+    std::os::unix::fs::symlink(&target, &link).unwrap();
+    run(&mut calvest(&[
+        "--input",
+        fixture("client_a.ics").to_str().unwrap(),
+        "--output",
+        link.to_str().unwrap(),
+    ]));
+    assert!(fs::symlink_metadata(&link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(rows(&fs::read_to_string(&target).unwrap()).len(), 2);
+}
+
+/// Through a symlink, so that a failure cannot replace the `/dev/stdout`.
+#[cfg(unix)]
+#[test]
+fn output_to_dev_stdout() {
+    let dir = temp_dir("dev-stdout");
+    let link = dir.join("stdout.csv");
+    std::os::unix::fs::symlink("/dev/stdout", &link).unwrap();
+    let output = run(&mut calvest(&[
+        "--input",
+        fixture("client_a.ics").to_str().unwrap(),
+        "--output",
+        link.to_str().unwrap(),
+    ]));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("Date,"), "{stdout}");
+    assert_eq!(rows(&stdout).len(), 2, "{stdout}");
+    assert!(fs::symlink_metadata(&link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+}
+
+#[test]
+fn stdin_parses_as_the_input_file() {
+    let ics = fixture("client_a.ics");
+    let from_file = run(&mut calvest(&["--input", ics.to_str().unwrap()]));
+    let from_stdin = run(calvest(&[]).stdin(fs::File::open(&ics).unwrap()));
+    assert_eq!(
+        rows(&String::from_utf8(from_file.stdout.clone()).unwrap()).len(),
+        2
+    );
+    assert_eq!(from_stdin.stdout, from_file.stdout);
+}
+
+#[test]
+fn gzipped_input_is_decompressed() {
+    let plain = run(&mut calvest(&[
+        "--input",
+        fixture("client_a.ics").to_str().unwrap(),
+    ]));
+    let gzipped = run(&mut calvest(&[
+        "--input",
+        fixture("client_a.ics.gz").to_str().unwrap(),
+    ]));
+    assert_eq!(
+        rows(&String::from_utf8(gzipped.stdout.clone()).unwrap()).len(),
+        2
+    );
+    assert_eq!(gzipped.stdout, plain.stdout);
+}
+
+#[test]
+fn windows_1252_input_is_decoded() {
+    let output = run(&mut calvest(&[
+        "--input",
+        fixture("windows_1252.ics").to_str().unwrap(),
+        "--input-encoding",
+        "latin1",
+    ]));
+    let csv = String::from_utf8(output.stdout).unwrap();
+    assert!(csv.contains(",Café résumé – naïve,"), "{csv}");
+}
+
+#[test]
+fn bare_vevent_parses_with_lenient_input() {
+    let bare = fixture("bare_vevent.ics");
+    let strict = calvest(&["--input", bare.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(rows(&String::from_utf8(strict.stdout).unwrap()).is_empty());
+    let output = run(&mut calvest(&[
+        "--input",
+        bare.to_str().unwrap(),
+        "--lenient-input",
+    ]));
+    let csv = String::from_utf8(output.stdout).unwrap();
+    let rows = rows(&csv);
+    assert_eq!(rows.len(), 1, "{csv}");
+    assert!(rows[0].starts_with("2025-01-06,"), "{csv}");
+    assert!(rows[0].contains(",Pasted,0.50,"), "{csv}");
+}
+
+#[test]
+fn malformed_events_are_skipped_and_reported() {
+    let dir = temp_dir("malformed-events");
+    let output = dir.join("out.csv");
+    let result = calvest(&[
+        "--input",
+        fixture("one_malformed.ics").to_str().unwrap(),
+        "--output",
+        output.to_str().unwrap(),
+    ])
+    .output()
+    .unwrap();
+    assert!(!result.status.success());
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.contains("Skipped events: 1"), "{stderr}");
+    assert!(stderr.contains("no DTSTART"), "{stderr}");
+    let csv = fs::read_to_string(&output).unwrap();
+    assert_eq!(rows(&csv).len(), 2, "{csv}");
+}
+
+#[test]
+fn skipped_events_are_reported_before_failing_on_empty() {
+    let result = calvest(&[
+        "--input",
+        fixture("one_malformed.ics").to_str().unwrap(),
+        "--start-date",
+        "2026-01-01",
+        "--fail-on-empty",
+    ])
+    .output()
+    .unwrap();
+    assert!(!result.status.success());
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.contains("Skipped events: 1"), "{stderr}");
+    assert!(stderr.contains("No work entries collected"), "{stderr}");
+}
+
+#[test]
+fn allow_empty_overrides_fail_on_empty() {
+    let output = run(&mut calvest(&[
+        "--input",
+        fixture("client_a.ics").to_str().unwrap(),
+        "--start-date",
+        "2026-01-01",
+        "--fail-on-empty",
+        "--allow-empty",
+    ]));
+    let csv = String::from_utf8(output.stdout).unwrap();
+    assert!(csv.starts_with("Date,"), "{csv}");
+    assert!(rows(&csv).is_empty(), "{csv}");
+}
+
+#[test]
+fn floating_times_follow_the_timezone() {
+    let output = run(&mut calvest(&[
+        "--input",
+        fixture("floating.ics").to_str().unwrap(),
+        "--timezone",
+        "Asia/Tokyo",
+    ]));
+    let csv = String::from_utf8(output.stdout).unwrap();
+    let rows = rows(&csv);
+    assert_eq!(rows.len(), 1, "{csv}");
+    // 23:00 in Tokyo, not 23:00 UTC, which is 08:00 of the next day there.
+    assert!(rows[0].starts_with("2025-01-06,"), "{csv}");
+}
+
+#[test]
+fn included_property_colliding_with_a_column_fails() {
+    let ics = fixture("client_a.ics");
+    run(&mut calvest(&[
+        "--input",
+        ics.to_str().unwrap(),
+        "--include-property",
+        "UID",
+    ]));
+    let result = calvest(&[
+        "--input",
+        ics.to_str().unwrap(),
+        "--include-property",
+        "UID",
+        "--with-uid",
+    ])
+    .output()
+    .unwrap();
+    assert!(!result.status.success());
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.contains("collides with a column name"), "{stderr}");
+}
+
+/// The Date column of the CSV rows.
+fn dates(csv: &str) -> Vec<&str> {
+    rows(csv)
+        .into_iter()
+        .map(|row| row.split(',').next().unwrap())
+        .collect()
+}
+
+#[test]
+fn weekly_rrule_logs_each_occurrence() {
+    let output = run(&mut calvest(&[
+        "--input",
+        fixture("weekly.ics").to_str().unwrap(),
+    ]));
+    let csv = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        dates(&csv),
+        ["2025-01-06", "2025-01-13", "2025-01-20", "2025-01-27"],
+        "{csv}"
+    );
+}
+
+#[test]
+fn reverse_sort_lists_the_latest_first() {
+    let output = run(&mut calvest(&[
+        "--input",
+        fixture("weekly.ics").to_str().unwrap(),
+        "--sort",
+        "date",
+        "--reverse",
+    ]));
+    let csv = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        dates(&csv),
+        ["2025-01-27", "2025-01-20", "2025-01-13", "2025-01-06"],
+        "{csv}"
+    );
+}
+
+#[test]
+fn ignore_rrule_logs_only_the_first_occurrence() {
+    let output = run(&mut calvest(&[
+        "--input",
+        fixture("weekly.ics").to_str().unwrap(),
+        "--ignore-rrule",
+    ]));
+    let csv = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(dates(&csv), ["2025-01-06"], "{csv}");
+}
+
+#[test]
+fn invalid_task_regex_is_a_clean_error() {
+    let result = calvest(&[
+        "--input",
+        fixture("client_a.ics").to_str().unwrap(),
+        "--task",
+        "Review",
+        "Engine",
+        "EN",
+        "Babbage",
+        "[",
+    ])
+    .output()
+    .unwrap();
+    assert_eq!(result.status.code(), Some(2));
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.contains("Invalid --task regex '['"), "{stderr}");
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
+
+#[test]
+fn dst_gap_is_reported_with_the_event() {
+    let output = run(&mut calvest(&[
+        "--input",
+        fixture("dst_gap.ics").to_str().unwrap(),
+    ]));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "DTSTART \"20250330T013000\": nonexistent local time shifted to 2025-03-30 01:00:00 UTC. \
+             Event: UID=\"night-shift@example.com\" SUMMARY=Some(\"Night shift\")"
+        ),
+        "{stderr}"
+    );
+    let csv = String::from_utf8(output.stdout).unwrap();
+    // From 02:00 BST, 01:00 UTC, to 03:30 BST.
+    assert!(rows(&csv)[0].contains(",1.50,"), "{csv}");
+}
+
+#[test]
+fn http_input_is_fetched() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/calendar.ics", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request = vec![];
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim_end().is_empty() {
+                break;
+            }
+            request.push(line.trim_end().to_string());
+        }
+        let body = fs::read(fixture("client_a.ics")).unwrap();
+        let mut stream = reader.into_inner();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/calendar\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(&body).unwrap();
+        request
+    });
+    let output = run(&mut calvest(&[
+        "--input",
+        &url,
+        "--header",
+        "Authorization: Bearer secret",
+    ]));
+    let request = server.join().unwrap();
+    assert_eq!(request[0], "GET /calendar.ics HTTP/1.1");
+    assert!(
+        request
+            .iter()
+            .any(|line| line.eq_ignore_ascii_case("authorization: Bearer secret")),
+        "{request:?}"
+    );
+    let from_file = run(&mut calvest(&[
+        "--input",
+        fixture("client_a.ics").to_str().unwrap(),
+    ]));
+    assert_eq!(output.stdout, from_file.stdout);
+}
+
+#[test]
+fn json_format_writes_an_object_per_event() {
+    let output = run(&mut calvest(&[
+        "--input",
+        fixture("client_a.ics").to_str().unwrap(),
+        "--end-date",
+        "2025-01-06",
+        "--format",
+        "json",
+    ]));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([{
+            "Date": "2025-01-06",
+            "Client": "Babbage",
+            "Project": "Engine",
+            "Project Code": "EN",
+            "Task": "Development",
+            "Notes": "Kickoff",
+            "Hours": "1.00",
+            "First name": "Ada",
+            "Last name": "Lovelace",
+        }])
+    );
+}
+
+#[test]
+fn missing_start_and_end_are_reported_together() {
+    let result = calvest(&["--input", fixture("no_start_no_end.ics").to_str().unwrap()])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.contains("Skipped events: 1"), "{stderr}");
+    assert!(
+        stderr.contains("no DTSTART, no DTEND or DURATION"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("no-times@example.com"), "{stderr}");
+}
+
+#[test]
+fn duplicated_events_are_skipped_but_not_the_occurrences() {
+    let output = run(&mut calvest(&[
+        "--input",
+        fixture("duplicated.ics").to_str().unwrap(),
+    ]));
+    let csv = String::from_utf8(output.stdout).unwrap();
+    let rows = rows(&csv);
+    assert_eq!(
+        rows.iter().filter(|row| row.contains(",Kickoff,")).count(),
+        1
+    );
+    // The standup occurrences share the UID but not the start.
+    assert_eq!(
+        rows.iter()
+            .filter(|row| row.contains(",Standup,"))
+            .map(|row| row.split(',').next().unwrap())
+            .collect::<Vec<_>>(),
+        ["2025-01-06", "2025-01-07", "2025-01-08"]
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Duplicate events skipped: 1"), "{stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn post_hook_receives_the_output_and_the_stats() {
+    let dir = temp_dir("post-hook");
+    let output = dir.join("out.csv");
+    let record = dir.join("hook.txt");
+    let hook = format!(
+        "record() {{ printf '%s %s %s' \"$CALVEST_EVENTS\" \"$CALVEST_HOURS\" \"$1\" > '{}'; }}; record",
+        record.display()
+    );
+    run(&mut calvest(&[
+        "--input",
+        fixture("client_a.ics").to_str().unwrap(),
+        "--output",
+        output.to_str().unwrap(),
+        "--post-hook",
+        &hook,
+    ]));
+    assert_eq!(
+        fs::read_to_string(&record).unwrap(),
+        format!("2 1.50 {}", output.display())
+    );
+}
+
+#[test]
+fn warnings_beyond_max_warnings_are_suppressed() {
+    // The count of the skipped events and a line for each of the two.
+    let result = calvest(&[
+        "--input",
+        fixture("one_malformed.ics").to_str().unwrap(),
+        "--input",
+        fixture("no_start_no_end.ics").to_str().unwrap(),
+        "--max-warnings",
+        "1",
+    ])
+    .output()
+    .unwrap();
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.contains("WARN: Skipped events: 2"), "{stderr}");
+    assert!(!stderr.contains("no DTSTART"), "{stderr}");
+    assert!(
+        stderr.contains("WARN: (suppressed 2 further warnings)"),
+        "{stderr}"
+    );
+    assert_eq!(stderr.matches("WARN: ").count(), 2, "{stderr}");
+}
+
+#[test]
+fn also_json_matches_the_csv() {
+    let dir = temp_dir("also-json");
+    let output = dir.join("out.csv");
+    let json = dir.join("out.json");
+    run(&mut calvest(&[
+        "--input",
+        fixture("client_a.ics").to_str().unwrap(),
+        "--output",
+        output.to_str().unwrap(),
+        "--also-json",
+        json.to_str().unwrap(),
+    ]));
+    let csv = fs::read_to_string(&output).unwrap();
+    let json: Vec<serde_json::Map<String, serde_json::Value>> =
+        serde_json::from_str(&fs::read_to_string(&json).unwrap()).unwrap();
+    let header = csv.lines().next().unwrap().split(',').collect::<Vec<_>>();
+    assert_eq!(json.len(), rows(&csv).len(), "{csv}");
+    for (row, object) in rows(&csv).into_iter().zip(&json) {
+        let values = object
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            header
+                .iter()
+                .copied()
+                .zip(row.split(','))
+                .collect::<Vec<_>>()
+        );
+    }
+}
+
+#[test]
+fn dry_run_previews_the_first_rows() {
+    let dir = temp_dir("dry-run");
+    let output = dir.join("out.csv");
+    let result = run(&mut calvest(&[
+        "--input",
+        fixture("weekly.ics").to_str().unwrap(),
+        "--output",
+        output.to_str().unwrap(),
+        "--dry-run",
+        "--preview-count",
+        "2",
+    ]));
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert!(lines[0].starts_with("Date "), "{stdout}");
+    assert!(lines[1].starts_with("2025-01-06 "), "{stdout}");
+    assert!(lines[2].starts_with("2025-01-13 "), "{stdout}");
+    assert_eq!(lines[3], "... and 2 more", "{stdout}");
+    assert!(!output.exists());
+}
+
+#[test]
+fn summary_totals_the_hours_per_task() {
+    let output = run(&mut calvest(&[
+        "--input",
+        fixture("client_a.ics").to_str().unwrap(),
+        "--input",
+        fixture("weekly.ics").to_str().unwrap(),
+        "--task",
+        "Meetings",
+        "Engine",
+        "EN",
+        "Babbage",
+        "Sync|Standup",
+    ]));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let summary = stderr
+        .lines()
+        .skip_while(|line| !line.starts_with("Client "))
+        .take(4)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            "Client  | Project | Task        | Hours",
+            "Babbage | Engine  | Development |  1.00",
+            "Babbage | Engine  | Meetings    |  1.50",
+            "Total   |         |             |  2.50",
+        ],
+        "{stderr}"
+    );
+}
+
+#[test]
+fn duplicate_vevent_block_is_reported() {
+    let output = run(&mut calvest(&[
+        "--input",
+        fixture("duplicate_block.ics").to_str().unwrap(),
+    ]));
+    let csv = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(rows(&csv).len(), 1, "{csv}");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("WARN: Duplicate VEVENT block; the export may be corrupt."),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("WARN: Duplicate VEVENT blocks found: 1"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn suggest_tasks_lists_the_unmatched_summaries_by_time() {
+    let output = run(&mut calvest(&[
+        "--input",
+        fixture("client_a.ics").to_str().unwrap(),
+        "--input",
+        fixture("weekly.ics").to_str().unwrap(),
+        "--task",
+        "Planning",
+        "Engine",
+        "EN",
+        "Babbage",
+        "Kickoff",
+        "--suggest-tasks",
+    ]));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout.lines().collect::<Vec<_>>(),
+        [
+            "Hours | Count | Summary",
+            " 1.00 |     4 | Standup",
+            " 0.50 |     1 | Sync",
+        ],
+        "{stdout}"
+    );
+}
+
+#[test]
+fn max_rows_counts_the_final_rows() {
+    let dir = temp_dir("max-rows");
+    let output = dir.join("out.csv");
+    let one_day = fixture("one_day.ics");
+    let result = calvest(&[
+        "--input",
+        one_day.to_str().unwrap(),
+        "--output",
+        output.to_str().unwrap(),
+        "--max-rows",
+        "3",
+    ])
+    .output()
+    .unwrap();
+    assert!(!result.status.success());
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(
+        stderr.contains("4 work entries are more than 3 (--max-rows)"),
+        "{stderr}"
+    );
+    assert!(!output.exists());
+    // Aggregated, the four events of the day make a single row.
+    run(&mut calvest(&[
+        "--input",
+        one_day.to_str().unwrap(),
+        "--output",
+        output.to_str().unwrap(),
+        "--max-rows",
+        "3",
+        "--aggregate-daily",
+    ]));
+    assert_eq!(rows(&fs::read_to_string(&output).unwrap()).len(), 1);
+}
+
+#[test]
+fn hours_beyond_the_time_window_are_reported() {
+    let one_day = fixture("one_day.ics");
+    let args = [
+        "--input",
+        one_day.to_str().unwrap(),
+        "--start-date",
+        "2025-01-06",
+        "--end-date",
+        "2025-01-06",
+    ];
+    let stderr = |extra: &[&str]| {
+        let output = run(&mut calvest(&[&args[..], extra].concat()));
+        String::from_utf8(output.stderr).unwrap()
+    };
+    let within = stderr(&[]);
+    assert!(!within.contains("exceeds"), "{within}");
+    // Four events of 10 hours in a day.
+    let beyond = stderr(&["--task-hours", ".", "10"]);
+    assert!(
+        beyond
+            .contains("WARN: The total of 40.00 hours exceeds the 24.00 hours of the time window"),
+        "{beyond}"
+    );
+}
+
+#[test]
+fn quiet_suppresses_the_progress_output() {
+    let ics = fixture("client_a.ics");
+    let loud = run(&mut calvest(&["--input", ics.to_str().unwrap()]));
+    let stderr = String::from_utf8(loud.stderr).unwrap();
+    assert!(stderr.contains("Collecting events"), "{stderr}");
+    let quiet = run(&mut calvest(&["--input", ics.to_str().unwrap(), "--quiet"]));
+    let stderr = String::from_utf8(quiet.stderr).unwrap();
+    assert!(!stderr.contains("Collecting events"), "{stderr}");
+    assert_eq!(quiet.stdout, loud.stdout);
+}
+
+#[test]
+fn split_by_month_writes_a_file_per_month() {
+    let dir = temp_dir("split-by-month").join("months");
+    run(&mut calvest(&[
+        "--input",
+        fixture("two_months.ics").to_str().unwrap(),
+        "--split-by-month",
+        dir.to_str().unwrap(),
+    ]));
+    let mut files = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    files.sort();
+    assert_eq!(files, ["2025-01.csv", "2025-02.csv"]);
+    let january = fs::read_to_string(dir.join("2025-01.csv")).unwrap();
+    assert_eq!(dates(&january), ["2025-01-31"], "{january}");
+    let february = fs::read_to_string(dir.join("2025-02.csv")).unwrap();
+    assert_eq!(dates(&february), ["2025-02-03", "2025-02-04"], "{february}");
+}
+
+#[test]
+fn names_default_to_the_environment() {
+    let output = run(Command::new(env!("CARGO_BIN_EXE_calvest"))
+        .args(DEFAULT_TASK)
+        .args(["--input", fixture("client_a.ics").to_str().unwrap()])
+        .env("CALVEST_TIMEZONE", "UTC")
+        .env("CALVEST_FIRST_NAME", "Augusta")
+        .env("CALVEST_LAST_NAME", "King")
+        .env_remove("CALVEST_DEFAULT_TASK"));
+    let csv = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(rows(&csv).len(), 2, "{csv}");
+    assert!(
+        rows(&csv).iter().all(|row| row.ends_with(",Augusta,King")),
+        "{csv}"
+    );
+    // The command line takes precedence.
+    let output = run(
+        calvest(&["--input", fixture("client_a.ics").to_str().unwrap()])
+            .env("CALVEST_FIRST_NAME", "Augusta"),
+    );
+    let csv = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        rows(&csv).iter().all(|row| row.ends_with(",Ada,Lovelace")),
+        "{csv}"
+    );
+}